@@ -0,0 +1,112 @@
+use crate::*;
+
+/// Roles the owner can grant on top of its own powers.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Role {
+    /// Runs the pool: can pause and resume staking and ping.
+    Operator,
+    /// Watches the pool: can only pause staking.
+    Guardian,
+}
+
+#[near_bindgen]
+impl StakingPool {
+    /// Owner's method.
+    /// Grants the role to the account.
+    pub fn grant_role(&mut self, role: Role, account_id: ValidAccountId) {
+        self.assert_owner();
+        let account_id: AccountId = account_id.into();
+        if self.role_members_mut(role).insert(&account_id) {
+            log!("@{} was granted the {:?} role", account_id, role);
+        }
+    }
+
+    /// Owner's method.
+    /// Revokes the role from the account.
+    pub fn revoke_role(&mut self, role: Role, account_id: ValidAccountId) {
+        self.assert_owner();
+        let account_id: AccountId = account_id.into();
+        if self.role_members_mut(role).remove(&account_id) {
+            log!("@{} lost the {:?} role", account_id, role);
+        }
+    }
+
+    /// Gives up the role of the predecessor.
+    pub fn renounce_role(&mut self, role: Role) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.role_members_mut(role).remove(&account_id),
+            "The account doesn't have the {:?} role",
+            role
+        );
+        log!("@{} renounced the {:?} role", account_id, role);
+    }
+
+    /// Owner's method.
+    /// Proposes a new owner, who becomes the owner once it calls `accept_owner`. `None` cancels
+    /// the proposal.
+    pub fn propose_owner(&mut self, new_owner_id: Option<ValidAccountId>) {
+        self.assert_owner();
+        self.proposed_owner_id = new_owner_id.map(|account_id| account_id.into());
+    }
+
+    /// Makes the predecessor the owner, it should be the proposed owner.
+    pub fn accept_owner(&mut self) {
+        let account_id = env::predecessor_account_id();
+        assert_eq!(
+            self.proposed_owner_id.as_ref(),
+            Some(&account_id),
+            "Can only be called by the proposed owner"
+        );
+        log!("The ownership moved from @{} to @{}", self.owner_id, account_id);
+        self.owner_id = account_id;
+        self.proposed_owner_id = None;
+    }
+
+    pub fn get_owner_id(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    pub fn get_proposed_owner_id(&self) -> Option<AccountId> {
+        self.proposed_owner_id.clone()
+    }
+
+    /// Returns the accounts with the given role.
+    pub fn get_role_members(&self, role: Role) -> Vec<AccountId> {
+        self.role_members(role).to_vec()
+    }
+
+    pub fn has_role(&self, role: Role, account_id: ValidAccountId) -> bool {
+        self.role_members(role).contains(account_id.as_ref())
+    }
+}
+
+impl StakingPool {
+    /// Checks that the predecessor is the owner or has one of the given roles.
+    pub(crate) fn assert_owner_or_role(&self, roles: &[Role]) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            account_id == self.owner_id
+                || roles
+                    .iter()
+                    .any(|role| self.role_members(*role).contains(&account_id)),
+            "Can only be called by the owner or the {:?} role",
+            roles
+        );
+    }
+
+    fn role_members(&self, role: Role) -> &UnorderedSet<AccountId> {
+        match role {
+            Role::Operator => &self.operators,
+            Role::Guardian => &self.guardians,
+        }
+    }
+
+    fn role_members_mut(&mut self, role: Role) -> &mut UnorderedSet<AccountId> {
+        match role {
+            Role::Operator => &mut self.operators,
+            Role::Guardian => &mut self.guardians,
+        }
+    }
+}
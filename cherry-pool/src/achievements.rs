@@ -0,0 +1,210 @@
+use crate::*;
+use near_sdk::Gas;
+
+const GAS_FOR_NFT_MINT_SERIES: Gas = 30_000_000_000_000;
+const GAS_FOR_ON_ACHIEVEMENT_NFT_MINTED: Gas = 10_000_000_000_000;
+const NO_DEPOSIT: Balance = 0;
+/// The deposit forwarded to the NFT contract to pay the storage of an achievement NFT.
+pub const ACHIEVEMENT_NFT_STORAGE_DEPOSIT: Balance = 10_000_000_000_000_000_000_000;
+/// The number of draws an account needs tickets in for `Achievement::TenDraws`.
+const NUM_DRAWS_FOR_ACHIEVEMENT: u64 = 10;
+
+/// The minter interface of the NFT contract, the pool has to be one of its minters.
+#[ext_contract(ext_nft_minter)]
+pub trait NftMinter {
+    fn nft_mint_series(&mut self, series_id: String, receiver_id: AccountId) -> Token;
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Achievement {
+    FirstDeposit,
+    FirstWin,
+    TenDraws,
+}
+
+impl Achievement {
+    const ALL: [Achievement; 3] = [
+        Achievement::FirstDeposit,
+        Achievement::FirstWin,
+        Achievement::TenDraws,
+    ];
+
+    /// The series of the NFT contract the achievement NFTs are minted from.
+    pub fn series_id(&self) -> &'static str {
+        match self {
+            Achievement::FirstDeposit => "first_deposit",
+            Achievement::FirstWin => "first_win",
+            Achievement::TenDraws => "ten_draws",
+        }
+    }
+}
+
+/// The achievements of an account. It outlives the account, so an achievement NFT can only be
+/// claimed once.
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct AccountAchievements {
+    /// Whether the account has ever deposited at least the ticket price to the pool.
+    pub deposited: bool,
+    /// The achievements with an NFT minted or being minted.
+    pub claimed: Vec<Achievement>,
+    /// The number of draws the account had tickets in, up to its last loyalty points update.
+    pub draws: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AchievementStatus {
+    pub achievement: Achievement,
+    pub earned: bool,
+    pub claimed: bool,
+}
+
+/// Accounts earn achievements for their first deposit, their first prize and taking part in ten
+/// draws. The pool mints their NFTs when they are claimed, from the series of the same names on
+/// the NFT contract, which caps their supply.
+#[near_bindgen]
+impl StakingPool {
+    /// Mints the NFT of the given earned achievement to the account. The attached deposit pays
+    /// the storage of the NFT, the rest of it is refunded. A failed mint gives the storage
+    /// deposit back and the achievement can be claimed again.
+    #[payable]
+    pub fn claim_achievement_nft(&mut self, achievement: Achievement) -> Promise {
+        self.assert_not_emergency();
+        let account_id = env::predecessor_account_id();
+        self.assert_registered(&account_id);
+        assert!(
+            self.internal_has_achievement(&account_id, achievement),
+            "The account hasn't earned the {} achievement",
+            achievement.series_id()
+        );
+        let mut achievements = self.account_achievements.get(&account_id).unwrap_or_default();
+        assert!(
+            !achievements.claimed.contains(&achievement),
+            "The {} achievement NFT is already claimed",
+            achievement.series_id()
+        );
+        let deposit = env::attached_deposit();
+        assert!(
+            deposit >= ACHIEVEMENT_NFT_STORAGE_DEPOSIT,
+            "Requires attached deposit of at least {} yoctoNEAR",
+            ACHIEVEMENT_NFT_STORAGE_DEPOSIT
+        );
+        achievements.claimed.push(achievement);
+        self.account_achievements.insert(&account_id, &achievements);
+
+        // The attached deposit comes in and leaves in this call, forwarded or refunded, so the
+        // total balance stays as it was.
+        self.last_total_balance += deposit;
+        let refund = deposit - ACHIEVEMENT_NFT_STORAGE_DEPOSIT;
+        if refund > 0 {
+            Promise::new(account_id.clone()).transfer(refund);
+        }
+        self.last_total_balance -= refund + ACHIEVEMENT_NFT_STORAGE_DEPOSIT;
+        ext_nft_minter::nft_mint_series(
+            achievement.series_id().to_string(),
+            account_id.clone(),
+            &self.nft_contract_id,
+            ACHIEVEMENT_NFT_STORAGE_DEPOSIT,
+            GAS_FOR_NFT_MINT_SERIES,
+        )
+        .then(ext_self::on_achievement_nft_minted(
+            account_id,
+            achievement,
+            ACHIEVEMENT_NFT_STORAGE_DEPOSIT.into(),
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_ON_ACHIEVEMENT_NFT_MINTED,
+        ))
+    }
+
+    /// Callback of minting an achievement NFT. A failed mint lets the account claim it again.
+    pub fn on_achievement_nft_minted(
+        &mut self,
+        account_id: AccountId,
+        achievement: Achievement,
+        deposit: U128,
+    ) {
+        assert_eq!(
+            env::current_account_id(),
+            env::predecessor_account_id(),
+            "Can be called only as a callback"
+        );
+        if let PromiseResult::Successful(result) = env::promise_result(0) {
+            if let Ok(token) = near_sdk::serde_json::from_slice::<Token>(&result) {
+                PoolEvent::NftRewardClaimed(vec![NftData {
+                    account_id,
+                    token_id: token.token_id,
+                }])
+                .emit();
+            }
+            return;
+        }
+        if let Some(mut achievements) = self.account_achievements.get(&account_id) {
+            achievements.claimed.retain(|claimed| *claimed != achievement);
+            self.account_achievements.insert(&account_id, &achievements);
+        }
+        // The NFT contract refunds the deposit of the failed mint to the pool before the callback,
+        // and it leaves again to the account.
+        self.last_total_balance += deposit.0;
+        Promise::new(account_id.clone()).transfer(deposit.0);
+        self.last_total_balance -= deposit.0;
+        log!(
+            "Minting the {} achievement NFT for @{} failed, it can be claimed again",
+            achievement.series_id(),
+            account_id
+        );
+    }
+
+    /// Returns whether the given account has earned and claimed every achievement.
+    pub fn get_account_achievements(&self, account_id: ValidAccountId) -> Vec<AchievementStatus> {
+        let account_id: AccountId = account_id.into();
+        let achievements = self.account_achievements.get(&account_id).unwrap_or_default();
+        Achievement::ALL
+            .iter()
+            .map(|&achievement| AchievementStatus {
+                achievement,
+                earned: self.internal_has_achievement(&account_id, achievement),
+                claimed: achievements.claimed.contains(&achievement),
+            })
+            .collect()
+    }
+}
+
+impl StakingPool {
+    /// Records the deposit of the account for `Achievement::FirstDeposit`, it has to buy at least
+    /// one ticket.
+    pub(crate) fn internal_record_deposit(&mut self, account_id: &AccountId, amount: Balance) {
+        if amount < self.config.ticket_price {
+            return;
+        }
+        let mut achievements = self.account_achievements.get(account_id).unwrap_or_default();
+        if !achievements.deposited {
+            achievements.deposited = true;
+            self.account_achievements.insert(account_id, &achievements);
+        }
+    }
+
+    /// Adds the draws the account had tickets in for `Achievement::TenDraws`.
+    pub(crate) fn internal_record_draws(&mut self, account_id: &AccountId, draws: u64) {
+        let mut achievements = self.account_achievements.get(account_id).unwrap_or_default();
+        achievements.draws += draws;
+        self.account_achievements.insert(account_id, &achievements);
+    }
+
+    fn internal_has_achievement(&self, account_id: &AccountId, achievement: Achievement) -> bool {
+        let achievements = self.account_achievements.get(account_id).unwrap_or_default();
+        match achievement {
+            Achievement::FirstDeposit => achievements.deposited,
+            Achievement::FirstWin => !self
+                .account_wins
+                .get(account_id)
+                .unwrap_or_default()
+                .is_empty(),
+            Achievement::TenDraws => {
+                achievements.draws + self.internal_new_draws(account_id) >= NUM_DRAWS_FOR_ACHIEVEMENT
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::*;
+
+/// Unstaked balance can't be released by the protocol sooner than this.
+pub const MIN_NUM_EPOCHS_TO_UNLOCK: EpochHeight = 4;
+/// Draws can't be further apart than a year (two epochs a day).
+pub const MAX_DRAW_PERIOD_EPOCHS: EpochHeight = 730;
+
+/// Parameters of a pool product, e.g. a weekly, monthly or high-stakes pool.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolConfig {
+    /// The number of epochs between two prize draws.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub draw_period_epochs: EpochHeight,
+    /// The staked amount that buys one ticket.
+    #[serde(with = "crate::serialize::u128_dec_format")]
+    pub ticket_price: Balance,
+    /// The number of epochs unstaked balance stays locked before it can be withdrawn.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub num_epochs_to_unlock: EpochHeight,
+    /// The maximum total staked balance of the pool, `None` for no limit.
+    #[serde(with = "crate::serialize::option_u128_dec_format")]
+    pub pool_threshold: Option<Balance>,
+    /// The whitelisted NEP-141 token the pool takes deposits in, `None` for native NEAR.
+    pub deposit_token_id: Option<AccountId>,
+    /// Where the yield funding the prize pot comes from.
+    pub yield_source: YieldSource,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            draw_period_epochs: 14,
+            ticket_price: 10 * 10u128.pow(24),
+            num_epochs_to_unlock: MIN_NUM_EPOCHS_TO_UNLOCK,
+            pool_threshold: None,
+            deposit_token_id: None,
+            yield_source: YieldSource::NativeStaking,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn assert_valid(&self) {
+        assert!(
+            self.draw_period_epochs > 0 && self.draw_period_epochs <= MAX_DRAW_PERIOD_EPOCHS,
+            "The draw period should be between 1 and {} epochs",
+            MAX_DRAW_PERIOD_EPOCHS
+        );
+        assert!(self.ticket_price > 0, "The ticket price should be positive");
+        assert!(
+            self.num_epochs_to_unlock >= MIN_NUM_EPOCHS_TO_UNLOCK,
+            "Unstaked balance can't unlock in less than {} epochs",
+            MIN_NUM_EPOCHS_TO_UNLOCK
+        );
+        if let Some(pool_threshold) = self.pool_threshold {
+            assert!(pool_threshold > 0, "The pool threshold should be positive");
+        }
+        let yield_source_id = match &self.yield_source {
+            YieldSource::NativeStaking => None,
+            YieldSource::ExternalStakingPool { pool_id } => Some(pool_id),
+            YieldSource::TokenTransfers { account_id } => Some(account_id),
+        };
+        for account_id in self.deposit_token_id.iter().chain(yield_source_id) {
+            assert!(
+                env::is_valid_account_id(account_id.as_bytes()),
+                "The account ID {} is invalid",
+                account_id
+            );
+        }
+        assert_eq!(
+            self.deposit_token_id.is_some(),
+            matches!(self.yield_source, YieldSource::TokenTransfers { .. }),
+            "Token pools and only them get their yield from token transfers"
+        );
+    }
+}
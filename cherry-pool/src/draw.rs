@@ -0,0 +1,66 @@
+use crate::*;
+
+/// Prize tier shares are expressed in basis points of the prize pot.
+pub const FULL_PRIZE_BASIS_POINTS: u16 = 10_000;
+/// The maximum number of prize tiers, which is also the number of winners per draw.
+pub const MAX_PRIZE_TIERS: usize = 20;
+/// Prizes left unclaimed for this many draws expire and go back into the prize pot.
+pub const NUM_DRAWS_TO_CLAIM_PRIZE: u64 = 4;
+
+/// A prize paid to one of the winning tickets of a draw.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PrizePayout {
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub draw_id: DrawId,
+    /// Index of the prize tier, `0` being the grand prize.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub tier: u64,
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub ticket: u64,
+    pub account_id: AccountId,
+    #[serde(with = "crate::serialize::u128_dec_format")]
+    pub amount: Balance,
+}
+
+/// A past draw as kept in the draw history.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DrawRecord {
+    /// The randomness inputs, the number of tickets in play and the winning tickets.
+    pub seed: DrawSeed,
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub block_height: BlockHeight,
+    /// The prize pot split between the winners.
+    #[serde(with = "crate::serialize::u128_dec_format")]
+    pub prize_pot: Balance,
+    pub payouts: Vec<PrizePayout>,
+}
+
+/// A prize won by the account and waiting to be claimed.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingPrize {
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub draw_id: DrawId,
+    #[serde(with = "crate::serialize::u128_dec_format")]
+    pub amount: Balance,
+}
+
+pub(crate) fn assert_valid_prize_tiers(prize_tiers: &[u16]) {
+    assert!(!prize_tiers.is_empty(), "There should be at least one prize tier");
+    assert!(
+        prize_tiers.len() <= MAX_PRIZE_TIERS,
+        "There can't be more than {} prize tiers",
+        MAX_PRIZE_TIERS
+    );
+    assert!(
+        prize_tiers.iter().all(|share| *share > 0),
+        "Every prize tier should get a positive share"
+    );
+    assert!(
+        prize_tiers.iter().map(|share| u64::from(*share)).sum::<u64>()
+            <= u64::from(FULL_PRIZE_BASIS_POINTS),
+        "Prize tiers can't share more than the whole prize pot"
+    );
+}
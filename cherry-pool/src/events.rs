@@ -0,0 +1,142 @@
+use crate::*;
+
+/// The standard name of the pool events.
+pub const EVENT_STANDARD: &str = "cherry_pool";
+/// The version of the event schema. Bump it when an event data changes.
+pub const EVENT_STANDARD_VERSION: &str = "1.0.0";
+/// The share transfers are logged as the events of the NEP-141 standard.
+const FT_EVENT_STANDARD: &str = "nep141";
+const FT_EVENT_STANDARD_VERSION: &str = "1.0.0";
+/// The prefix of the NEP-297 event logs.
+const EVENT_JSON_PREFIX: &str = "EVENT_JSON:";
+
+/// The pool events, logged as NEP-297 events:
+/// `EVENT_JSON:{"standard":"cherry_pool","version":"1.0.0","event":"stake","data":[...]}`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum PoolEvent {
+    Deposit(Vec<AmountData>),
+    Stake(Vec<SharesData>),
+    Unstake(Vec<SharesData>),
+    Withdraw(Vec<AmountData>),
+    TicketsAdded(Vec<TicketsData>),
+    TicketsRemoved(Vec<TicketsData>),
+    DrawStarted(Vec<DrawStartedData>),
+    WinnerSelected(Vec<WinnerSelectedData>),
+    PrizePaid(Vec<PrizePaidData>),
+    NftBoostApplied(Vec<NftData>),
+    NftBoostRemoved(Vec<NftData>),
+    NftRewardClaimed(Vec<NftData>),
+    RewardDistributed(Vec<RewardData>),
+    FtTransfer(Vec<FtTransferData>),
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AmountData {
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SharesData {
+    pub account_id: AccountId,
+    pub amount: U128,
+    pub shares: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TicketsData {
+    pub account_id: AccountId,
+    pub tickets: U64,
+    /// The number of tickets of the account after the change.
+    pub total_tickets: U64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DrawStartedData {
+    pub draw_id: U64,
+    pub tickets_count: U64,
+    /// Set for commit-reveal draws, the winners are picked once this block is reached.
+    pub reveal_block_height: Option<U64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WinnerSelectedData {
+    pub draw_id: U64,
+    pub tier: U64,
+    pub ticket: U64,
+    pub account_id: AccountId,
+    pub amount: U128,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PrizePaidData {
+    pub account_id: AccountId,
+    pub amount: U128,
+    /// Whether the prize was staked instead of transferred.
+    pub restaked: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftData {
+    pub account_id: AccountId,
+    pub token_id: TokenId,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RewardData {
+    pub epoch_height: U64,
+    pub total_reward: U128,
+    /// The prize pot after the reward.
+    pub prize_pot: U128,
+    /// The stake shares bought with the owner's and the treasury's fees.
+    pub owner_fee_shares: U128,
+    pub treasury_fee_shares: U128,
+}
+
+/// A transfer of stake shares, in the NEP-141 event format.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtTransferData {
+    pub old_owner_id: AccountId,
+    pub new_owner_id: AccountId,
+    pub amount: U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a PoolEvent,
+}
+
+impl PoolEvent {
+    /// Logs the event.
+    pub fn emit(&self) {
+        let (standard, version) = match self {
+            PoolEvent::FtTransfer(_) => (FT_EVENT_STANDARD, FT_EVENT_STANDARD_VERSION),
+            _ => (EVENT_STANDARD, EVENT_STANDARD_VERSION),
+        };
+        let event_log = EventLog {
+            standard,
+            version,
+            event: self,
+        };
+        let json = near_sdk::serde_json::to_string(&event_log)
+            .unwrap_or_else(|_| env::panic(b"Failed to serialize the event"));
+        env::log(format!("{}{}", EVENT_JSON_PREFIX, json).as_bytes());
+    }
+}
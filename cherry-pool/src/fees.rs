@@ -0,0 +1,89 @@
+use crate::*;
+
+/// The owner fee and the treasury fee together can't take more than this share of the rewards.
+pub const MAX_REWARD_FEE_FRACTION: RewardFeeFraction = RewardFeeFraction {
+    numerator: 20,
+    denominator: 100,
+};
+
+/// A share of the epoch rewards.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RewardFeeFraction {
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl RewardFeeFraction {
+    pub fn assert_valid(&self) {
+        assert_ne!(self.denominator, 0, "Denominator must be a positive number");
+        assert!(
+            self.numerator <= self.denominator,
+            "The reward fee must be less or equal to 1"
+        );
+    }
+
+    pub fn multiply(&self, value: Balance) -> Balance {
+        (U256::from(self.numerator) * U256::from(value) / U256::from(self.denominator)).as_u128()
+    }
+}
+
+/// The protocol treasury and the share of the epoch rewards it receives.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TreasuryFee {
+    pub account_id: AccountId,
+    pub fee_fraction: RewardFeeFraction,
+}
+
+/// How the reward of one epoch is split.
+pub(crate) struct RewardSplit {
+    pub owner_fee: Balance,
+    pub treasury_fee: Balance,
+    pub prize_pot: Balance,
+}
+
+/// Checks both fees and that together they stay within `MAX_REWARD_FEE_FRACTION`.
+pub(crate) fn assert_valid_fees(reward_fee_fraction: &RewardFeeFraction, treasury: &Option<TreasuryFee>) {
+    reward_fee_fraction.assert_valid();
+    // Fees are compared as `a/b + c/d <= m/n`, i.e. `(a*d + c*b) * n <= m * b * d`.
+    let (mut numerator, mut denominator) = (
+        u128::from(reward_fee_fraction.numerator),
+        u128::from(reward_fee_fraction.denominator),
+    );
+    if let Some(treasury) = treasury {
+        treasury.fee_fraction.assert_valid();
+        assert!(
+            env::is_valid_account_id(treasury.account_id.as_bytes()),
+            "The treasury account ID is invalid"
+        );
+        let fee_fraction = &treasury.fee_fraction;
+        numerator = numerator * u128::from(fee_fraction.denominator)
+            + u128::from(fee_fraction.numerator) * denominator;
+        denominator *= u128::from(fee_fraction.denominator);
+    }
+    assert!(
+        numerator * u128::from(MAX_REWARD_FEE_FRACTION.denominator)
+            <= u128::from(MAX_REWARD_FEE_FRACTION.numerator) * denominator,
+        "The reward fees can't take more than {}/{} of the rewards",
+        MAX_REWARD_FEE_FRACTION.numerator,
+        MAX_REWARD_FEE_FRACTION.denominator
+    );
+}
+
+pub(crate) fn split_reward(
+    reward: Balance,
+    reward_fee_fraction: &RewardFeeFraction,
+    treasury: &Option<TreasuryFee>,
+) -> RewardSplit {
+    let owner_fee = reward_fee_fraction.multiply(reward);
+    let treasury_fee = treasury
+        .as_ref()
+        .map(|treasury| treasury.fee_fraction.multiply(reward))
+        .unwrap_or(0);
+    RewardSplit {
+        owner_fee,
+        treasury_fee,
+        prize_pot: reward - owner_fee - treasury_fee,
+    }
+}
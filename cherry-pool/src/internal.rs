@@ -1,10 +1,7 @@
 use crate::*;
-use rand::prelude::*;
-use rand_chacha::ChaCha8Rng;
-use std::convert::TryInto;
+use crate::randomness::seed_commitment;
 const ON_STAKE_ACTION_GAS: u64 = 20_000_000_000_000;
 const NO_DEPOSIT: Balance = 0;
-const NUM_EPOCHS_TO_UNLOCK: EpochHeight = 4;
 
 
 
@@ -23,63 +20,99 @@ impl StakingPool {
         let total_balance =
             env::account_locked_balance() + env::account_balance() - env::attached_deposit();
 
-        assert!(
-            total_balance >= self.last_total_balance,
-            "The new total balance should not be less than the old total balance"
-        );
-        let total_reward = total_balance - self.last_total_balance;
-
-        if total_reward > 0 {
-            // The validation fee that the contract owner takes.
-            // let owners_fee = self.reward_fee_fraction.multiply(total_reward);
-
-            // Distributing the remaining reward to the delegators first.
-            // let remaining_reward = total_reward - owners_fee;
-            self.total_reward += total_reward;
-            self.total_staked_balance += total_reward;
-
-            // Now buying "stake" shares for the contract owner at the new share price.
-            // let num_shares = self.num_shares_from_staked_amount_rounded_down(owners_fee);
-            // if num_shares > 0 {
-            //     // Updating owner's inner account
-            //     let owner_id = self.owner_id.clone();
-            //     let mut account = self.internal_get_account(&owner_id);
-            //     account.stake_shares += num_shares;
-            //     self.internal_save_account(&owner_id, &account);
-            //     // Increasing the total amount of "stake" shares.
-            //     self.total_stake_shares += num_shares;
-            // }
-            // Increasing the total staked balance by the owners fee, no matter whether the owner
-            // received any shares or not.
-            // self.total_staked_balance += owners_fee;
-
-            env::log(
-                format!(
-                    "Epoch {}: Contract received total rewards of {} tokens. New total staked balance is {}",
-                    epoch_height, total_reward, self.total_staked_balance,
-                )
-                    .as_bytes(),
+        // Only a validator earns its rewards in its own balance. An external staking pool reports
+        // them asynchronously and a token pool earns them in the deposit token.
+        if self.is_native_staking() {
+            assert!(
+                total_balance >= self.last_total_balance,
+                "The new total balance should not be less than the old total balance"
             );
-            // if num_shares > 0 {
-            //     env::log(format!("Total rewards fee is {} stake shares.", num_shares).as_bytes());
-            // }
+            let total_reward = total_balance - self.last_total_balance;
+            if total_reward > 0 {
+                self.internal_distribute_reward(total_reward);
+            }
+        } else {
+            self.internal_collect_external_yield();
         }
 
-        if self.next_prize_event_epoch_height <= env::epoch_height() {
-            let winner = self.get_prize_winner();
-            self.transfer_prize_to_winner(winner);
-            self.next_prize_event_epoch_height = env::epoch_height() + 14;
+        // In commit-reveal mode the draw is started by `ping` and finished by `reveal_draw`, so it
+        // never happens inline with somebody's stake.
+        if self.draw_reveal_delay.is_none() && self.is_prize_draw_due() {
+            self.internal_draw_prize();
+        } else if self.are_draws_active()
+            && self.pending_draw.is_none()
+            && self.next_prize_event_epoch_height <= env::epoch_height()
+            && self.tickets.total_weight() == 0
+        {
+            // Nobody held tickets during the draw period, the prize pot rolls over to the next one.
+            self.internal_schedule_next_draw();
         }
 
         self.last_total_balance = total_balance;
         true
     }
 
-    pub(crate) fn internal_stake(&mut self, amount: Balance) {
+    /// Splits the reward between the prize pot and the validator fees.
+    pub(crate) fn internal_distribute_reward(&mut self, total_reward: Balance) {
+        // The validator fees are restaked for the owner and the treasury. The rest of the reward
+        // funds the prize pot instead of raising the "stake" share price. It's left out of the
+        // restaked amount so it becomes liquid for prize claims.
+        let split = split_reward(total_reward, &self.reward_fee_fraction, &self.treasury);
+        self.total_reward += split.prize_pot;
+
+        let owner_id = self.owner_id.clone();
+        let owner_shares = self.internal_buy_fee_shares(&owner_id, split.owner_fee);
+        let treasury_shares = match self.treasury.clone() {
+            Some(treasury) => self.internal_buy_fee_shares(&treasury.account_id, split.treasury_fee),
+            None => 0,
+        };
+
+        PoolEvent::RewardDistributed(vec![RewardData {
+            epoch_height: env::epoch_height().into(),
+            total_reward: total_reward.into(),
+            prize_pot: self.total_reward.into(),
+            owner_fee_shares: owner_shares.into(),
+            treasury_fee_shares: treasury_shares.into(),
+        }])
+        .emit();
+    }
+
+    /// Buys "stake" shares for the fee receiver at the current share price and returns their
+    /// number. Fee shares don't come with tickets. An unregistered receiver is registered with
+    /// the storage deposit taken from its fee, a smaller fee raises the share price instead.
+    pub(crate) fn internal_buy_fee_shares(&mut self, account_id: &AccountId, mut fee: Balance) -> Balance {
+        if fee == 0 {
+            return 0;
+        }
+        if !self.is_registered(account_id) {
+            let storage_cost = self.registration_cost(account_id);
+            if fee <= storage_cost {
+                self.total_staked_balance += fee;
+                return 0;
+            }
+            // The storage deposit is left out of the restaked amount, like the prize pot.
+            self.internal_register_account(account_id);
+            fee -= storage_cost;
+        }
+        let num_shares = self.num_shares_from_staked_amount_rounded_down(fee);
+        if num_shares > 0 {
+            self.internal_update_loyalty_points(account_id);
+            let mut account = self.internal_get_account(account_id.clone());
+            account.stake_shares += num_shares;
+            self.save_account(&account);
+            self.total_stake_shares += num_shares;
+        }
+        // Increasing the total staked balance by the fee, no matter whether the receiver got any
+        // shares or not.
+        self.total_staked_balance += fee;
+        num_shares
+    }
+
+    pub(crate) fn internal_stake(&mut self, account_id: &AccountId, amount: Balance) {
         assert!(amount > 0, "Staking amount should be positive");
 
-        let account_id = env::predecessor_account_id();
-        let mut account = self.get_account(account_id);
+        self.internal_update_loyalty_points(account_id);
+        let mut account = self.internal_get_account(account_id.clone());
 
         // Calculate the number of "stake" shares that the account will receive for staking the
         // given amount.
@@ -103,92 +136,149 @@ impl StakingPool {
         );
         account.unstaked_balance -= charge_amount;
         account.stake_shares += num_shares;
-        account.stake_points += 1;
         self.save_account(&account);
 
         // The staked amount that will be added to the total to guarantee the "stake" share price
         // never decreases. The difference between `stake_amount` and `charge_amount` is paid
         // from the allocated STAKE_SHARE_PRICE_GUARANTEE_FUND.
         let stake_amount = self.staked_amount_from_num_shares_rounded_up(num_shares);
+        if let Some(pool_threshold) = self.config.pool_threshold {
+            assert!(
+                self.total_staked_balance + stake_amount <= pool_threshold,
+                "The pool is full"
+            );
+        }
 
         self.total_staked_balance += stake_amount;
         self.total_stake_shares += num_shares;
 
-        env::log(
-            format!(
-                "@{} staking {}. Received {} new staking shares. Total {} unstaked balance and {} staking shares",
-                account.account_id, charge_amount, num_shares, account.unstaked_balance, account.stake_shares
-            )
-                .as_bytes(),
-        );
-        env::log(
-            format!(
-                "Contract total staked balance is {}. Total number of shares {}",
-                self.total_staked_balance, self.total_stake_shares
-            )
-            .as_bytes(),
-        );
+        PoolEvent::Stake(vec![SharesData {
+            account_id: account.account_id,
+            amount: charge_amount.into(),
+            shares: num_shares.into(),
+        }])
+        .emit();
     } 
 
-    pub(crate) fn internal_add_tickets(&mut self, amount: u128, multiplier: u64) {
+    /// Adds the tickets bought with the given amount and the bonus tickets of the account's NFT
+    /// boost for them.
+    pub(crate) fn internal_add_tickets(&mut self, account_id: &AccountId, amount: u128) {
         assert!(amount > 0, "Staking amount should be positive");
         
-        let tickets_amount = amount / MIN_TICKET_DEPOSIT_PRICE;
-        let mut tickets_num = tickets_amount as u64;
-        
+        let tickets_num = (amount / self.config.ticket_price) as u64;
+        self.internal_give_tickets(account_id, tickets_num);
+        self.internal_update_nft_bonus(account_id);
+    }
 
-        let mut account = self.get_account(env::predecessor_account_id());
-        tickets_num = tickets_num * multiplier;
+    /// Adds the given number of tickets to the account.
+    pub(crate) fn internal_give_tickets(&mut self, account_id: &AccountId, tickets_num: u64) {
+        self.assert_tickets_unlocked();
+
+        self.internal_update_loyalty_points(account_id);
+        let mut account = self.internal_get_account(account_id.clone());
         account.tickets_amount += tickets_num;
         self.save_account(&account);
-        println!("tickets added: {}", tickets_num);
-        
-        let next_tickets_count = self.tickets_count + tickets_num;
-        let mut optional = Some(self.tickets_count);
-
-        while let Some(i) = optional {
-            if i >= next_tickets_count {
-                optional = None;
-            } else {
-                self.tickets_random_slots.push(&account.account_id);
-                optional = Some(i + 1);
-            }
-        }
-        self.tickets_count = next_tickets_count;
+
+        self.tickets.add(&account.account_id, tickets_num);
+        PoolEvent::TicketsAdded(vec![TicketsData {
+            account_id: account.account_id,
+            tickets: tickets_num.into(),
+            total_tickets: account.tickets_amount.into(),
+        }])
+        .emit();
     }
 
-    pub(crate) fn internal_remove_tickets(&mut self, amount: u128) {
+    /// Removes the tickets bought with the given amount and the bonus tickets of the predecessor's
+    /// NFT boost for them. Returns the number of the removed tickets without the bonus.
+    pub(crate) fn internal_remove_tickets(&mut self, amount: u128) -> u64 {
         assert!(amount > 0, "Staking amount should be positive");
+        self.assert_tickets_unlocked();
         
-        let tickets_amount = amount / MIN_TICKET_DEPOSIT_PRICE;
-        let mut tickets_num = tickets_amount as u64;
+        let account_id = env::predecessor_account_id();
+        let account = self.internal_get_account(account_id.clone());
+        // Rounding down every stake can leave the account fewer tickets than its staked balance
+        // buys.
+        let tickets_num = std::cmp::min(
+            (amount / self.config.ticket_price) as u64,
+            account.tickets_amount - self.internal_nft_bonus_tickets(&account),
+        );
+
+        let tickets_removed = self.internal_take_tickets(account, tickets_num);
+        self.internal_update_nft_bonus(&account_id);
+        tickets_removed
+    }
+
+    /// Removes all the tickets of the predecessor and returns their number. Unlike removing by
+    /// amount, it doesn't leave a ticket behind when the staked balance got rounded down.
+    pub(crate) fn internal_remove_all_tickets(&mut self) -> u64 {
+        self.assert_tickets_unlocked();
+        let account_id = env::predecessor_account_id();
+        let account = self.internal_get_account(account_id.clone());
+        let tickets_num = account.tickets_amount;
+        let tickets_removed = self.internal_take_tickets(account, tickets_num);
+        self.internal_update_nft_bonus(&account_id);
+        tickets_removed
+    }
 
-        let mut account = self.get_account(env::predecessor_account_id());
-        tickets_num = tickets_num * account.tickets_multiplier;
+    pub(crate) fn internal_take_tickets(&mut self, mut account: StakingPoolAccount, tickets_num: u64) -> u64 {
+        self.internal_update_loyalty_points(&account.account_id);
         account.tickets_amount -= tickets_num;
-        account.tickets_multiplier = 1;
         self.save_account(&account);
 
-        println!("tickets removed: {}", tickets_amount);
+        if tickets_num > 0 {
+            self.tickets.remove(&account.account_id, tickets_num);
+            PoolEvent::TicketsRemoved(vec![TicketsData {
+                account_id: account.account_id,
+                tickets: tickets_num.into(),
+                total_tickets: account.tickets_amount.into(),
+            }])
+            .emit();
+        }
+        tickets_num
+    }
 
-        let next_tickets_count = self.tickets_count - tickets_num;
+    pub(crate) fn assert_tickets_unlocked(&self) {
+        assert!(
+            self.pending_draw.is_none(),
+            "Tickets are locked until the pending draw is revealed"
+        );
+    }
 
-        for i in 0..next_tickets_count {
-            let ticket_owner = self.tickets_random_slots.get(i as u64).unwrap();
-            if ticket_owner == account.account_id && tickets_num > 0 {
-                self.tickets_random_slots.swap_remove(i as u64);
-                tickets_num -= 1;
+    /// Checks that the tickets of every account match its slot in the ledger, that they add up to
+    /// the ledger total, and that the bonus tickets of the NFT boosts are part of them.
+    #[cfg(test)]
+    pub(crate) fn assert_tickets_invariant(&self) {
+        let mut total_tickets = 0;
+        for (account_id, slot_tickets) in self.tickets.slots() {
+            let account = self.internal_get_account(account_id.clone());
+            assert_eq!(
+                account.tickets_amount, slot_tickets,
+                "The tickets of @{} don't match its slot",
+                account_id
+            );
+            if let Some(nft_boost) = self.nft_boosts.get(&account_id) {
+                assert!(
+                    nft_boost.bonus_tickets <= account.tickets_amount,
+                    "The bonus tickets of @{} exceed its tickets",
+                    account_id
+                );
             }
+            total_tickets += account.tickets_amount;
         }
-
-        self.tickets_count = next_tickets_count;
+        assert_eq!(
+            total_tickets,
+            self.tickets.total(),
+            "The tickets of the accounts don't add up to the total"
+        );
     }
 
-    pub(crate) fn internal_unstake(&mut self, amount: u128) {
+    /// Unstakes the given amount and returns the amount added to the unstaked balance.
+    pub(crate) fn internal_unstake(&mut self, amount: u128) -> Balance {
         assert!(amount > 0, "Unstaking amount should be positive");
 
         let account_id = env::predecessor_account_id();
-        let mut account = self.get_account(account_id);
+        self.internal_update_loyalty_points(&account_id);
+        let mut account = self.internal_get_account(account_id);
 
         assert!(
             self.total_staked_balance > 0,
@@ -216,8 +306,7 @@ impl StakingPool {
 
         account.stake_shares -= num_shares;
         account.unstaked_balance += receive_amount;
-        account.stake_points -= 1;
-        account.unstaked_available_epoch_height = env::epoch_height() + NUM_EPOCHS_TO_UNLOCK;
+        account.unstaked_available_epoch_height = env::epoch_height() + self.config.num_epochs_to_unlock;
         self.save_account(&account);
 
         // The amount tokens that will be unstaked from the total to guarantee the "stake" share
@@ -228,35 +317,79 @@ impl StakingPool {
         self.total_staked_balance -= unstake_amount;
         self.total_stake_shares -= num_shares;
 
-        env::log(
-            format!(
-                "@{} unstaking {}. Spent {} staking shares. Total {} unstaked balance and {} staking shares",
-                account.account_id, receive_amount, num_shares, account.unstaked_balance, account.stake_shares
-            )
-                .as_bytes(),
+        PoolEvent::Unstake(vec![SharesData {
+            account_id: account.account_id,
+            amount: receive_amount.into(),
+            shares: num_shares.into(),
+        }])
+        .emit();
+        receive_amount
+    }
+
+    /// Transfers the given amount of the predecessor's unstaked balance once it's unlocked.
+    pub(crate) fn internal_withdraw(&mut self, amount: Balance) -> WithdrawResult {
+        assert!(amount > 0, "Withdrawal amount should be positive");
+
+        let mut account = self.internal_get_account(env::predecessor_account_id());
+        assert!(
+            account.unstaked_balance >= amount,
+            "Not enough unstaked balance to withdraw"
+        );
+        assert!(
+            account.can_withdraw,
+            "The unstaked balance is not yet available due to unstaking delay"
         );
-        env::log(
-            format!(
-                "Contract total staked balance is {}. Total number of shares {}",
-                self.total_staked_balance, self.total_stake_shares
-            )
-            .as_bytes(),
+        // The balance unstaked from an external pool stays there until it's withdrawn on ping.
+        assert!(
+            self.is_token_pool() || env::account_balance() >= amount,
+            "The unstaked balance is still being withdrawn, try again later"
         );
+        account.unstaked_balance -= amount;
+        self.save_account(&account);
+
+        PoolEvent::Withdraw(vec![AmountData {
+            account_id: account.account_id.clone(),
+            amount: amount.into(),
+        }])
+        .emit();
+
+        self.internal_send(account.account_id.clone(), amount);
+
+        WithdrawResult {
+            account_id: account.account_id,
+            amount: amount.into(),
+            unstaked_balance: account.unstaked_balance.into(),
+        }
     }
 
     pub(crate) fn internal_restake(&mut self) {
         if self.is_restake_paused {
             return;
         }
-        // Stakes with the staking public key. If the public key is invalid the entire function
-        // call will be rolled back.
-        Promise::new(env::current_account_id())
-            .stake(self.total_staked_balance, self.stake_public_key.clone())
-            .then(ext_self::on_stake_action(
-                &env::current_account_id(),
-                NO_DEPOSIT,
-                ON_STAKE_ACTION_GAS,
-            ));
+        match self.config.yield_source.clone() {
+            YieldSource::NativeStaking => {
+                // Stakes with the staking public key. If the public key is invalid the entire
+                // function call will be rolled back.
+                Promise::new(env::current_account_id())
+                    .stake(self.total_staked_balance, self.stake_public_key.clone())
+                    .then(ext_self::on_stake_action(
+                        &env::current_account_id(),
+                        NO_DEPOSIT,
+                        ON_STAKE_ACTION_GAS,
+                    ));
+            }
+            YieldSource::ExternalStakingPool { pool_id } => self.internal_restake_external(pool_id),
+            // A token pool doesn't stake, its deposits stay in the deposit token.
+            YieldSource::TokenTransfers { .. } => {}
+        }
+    }
+
+    pub(crate) fn assert_owner(&self) {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.owner_id,
+            "Can only be called by the owner"
+        );
     }
 
     pub(crate) fn num_shares_from_staked_amount_rounded_down(
@@ -321,139 +454,197 @@ impl StakingPool {
         .as_u128()
     }
 
-    // pub(crate) fn random_winners(&mut self) {
-    //     self.random_u64();
-    // }
-    pub(crate) fn get_prize_winner(&mut self) -> AccountId {
-        assert!(self.next_prize_event_epoch_height <= env::epoch_height(), "Next prize event time not reached");
-        let ticket_id = self.random_u64(0, self.tickets_count);
-        println!("Winning Ticket: {}", ticket_id);
-        let winner = self.tickets_random_slots.get(ticket_id as u64).unwrap();
-        winner
+    pub(crate) fn is_prize_draw_due(&self) -> bool {
+        self.are_draws_active()
+            && self.pending_draw.is_none()
+            && self.pending_share_transfers == 0
+            && self.next_prize_event_epoch_height <= env::epoch_height()
+            && self.tickets.total_weight() > 0
     }
 
-    pub(crate) fn transfer_prize_to_winner(&mut self, winner: AccountId) -> Promise {
-        assert!(self.next_prize_event_epoch_height <= env::epoch_height(), "Next prize event time not reached");
-        let prize = self.total_reward;
-        self.total_reward = 0;
-        Promise::new(winner).transfer(prize)
+    /// Locks the ticket snapshot for a commit-reveal draw.
+    pub(crate) fn internal_commit_draw(&mut self) {
+        let reveal_delay = self.draw_reveal_delay.expect("Commit-reveal draws are disabled");
+        let draw_id = self.next_draw_id;
+        self.next_draw_id += 1;
+
+        let pending_draw = PendingDraw::new(draw_id, self.tickets.total_weight(), env::block_index() + reveal_delay);
+        PoolEvent::DrawStarted(vec![DrawStartedData {
+            draw_id: draw_id.into(),
+            tickets_count: pending_draw.tickets_count.into(),
+            reveal_block_height: Some(pending_draw.reveal_block_height.into()),
+        }])
+        .emit();
+        self.pending_draw = Some(pending_draw);
     }
 
-    pub(crate) fn random_u64(&self, min_inc: u64, max_exc: u64) -> u64 {
-        // Returns a random number between min (included) and max (excluded)
-        let seed_vec = env::random_seed();
-        let mut seed: <ChaCha8Rng as SeedableRng>::Seed = env::sha256(&seed_vec.to_owned()).try_into().unwrap();
-        thread_rng().fill(&mut seed);
-        let mut rng = ChaCha8Rng::from_seed(seed);
-        let random = rng.next_u64() % (max_exc - min_inc) + min_inc;
-        random
+    /// Picks the winners, pays the prizes and schedules the next prize event.
+    pub(crate) fn internal_draw_prize(&mut self) -> Vec<PrizePayout> {
+        let (draw_id, winners) = self.get_prize_winners();
+        let payouts = self.award_prizes_to_winners(draw_id, winners);
+        self.internal_schedule_next_draw();
+        payouts
     }
 
-    pub(crate) fn validate_nft_owner(&mut self) -> Promise {
-        ext_nft_enumeration::nft_tokens_for_owner(
-            env::predecessor_account_id(),
-            None,
-            None,
-            &self.nft_contract_id,
-            0,
-            5_000_000_000_000
-        )
-        .then(ext_self::nft_valid_callback(
-            &env::current_account_id(), // this contract's account id
-            0, // yocto NEAR to attach to the callback
-            5_000_000_000_000 // gas to attach to the callback
-        ))
+    /// Schedules the next prize event and starts a new draw period for the ticket odds.
+    pub(crate) fn internal_schedule_next_draw(&mut self) {
+        let draw_period_epochs = self.config.draw_period_epochs;
+        self.next_prize_event_epoch_height = env::epoch_height() + draw_period_epochs;
+        self.tickets.start_period(env::epoch_height(), draw_period_epochs);
     }
 
-    pub fn nft_valid_callback(&self) -> bool {
+    /// Draws the winning tickets of the pending commit-reveal draw if there is one, otherwise
+    /// draws inline from the current tickets. Every prize tier gets a distinct ticket.
+    pub(crate) fn get_prize_winners(&mut self) -> (DrawId, Vec<(u64, AccountId)>) {
+        assert!(self.next_prize_event_epoch_height <= env::epoch_height(), "Next prize event time not reached");
+        let tickets_count = self.tickets.total_weight();
+        assert!(tickets_count > 0, "There are no tickets to draw from");
+        let draw_id = match self.pending_draw.take() {
+            Some(pending_draw) => {
+                pending_draw.assert_revealable(tickets_count);
+                pending_draw.draw_id
+            }
+            None => {
+                let draw_id = self.next_draw_id;
+                self.next_draw_id += 1;
+                PoolEvent::DrawStarted(vec![DrawStartedData {
+                    draw_id: draw_id.into(),
+                    tickets_count: tickets_count.into(),
+                    reveal_block_height: None,
+                }])
+                .emit();
+                draw_id
+            }
+        };
+
+        let winners_count = std::cmp::min(self.prize_tiers.len() as u64, tickets_count);
+        let draw_seed = DrawSeed::new(
+            env::random_seed(),
+            draw_id,
+            env::epoch_height(),
+            tickets_count,
+            winners_count,
+        );
         assert_eq!(
-            env::promise_results_count(),
-            1,
-            "This is a callback method"
+            draw_seed.seed_commitment,
+            seed_commitment(&env::random_seed(), env::epoch_height()),
+            "The draw seed doesn't match the reveal block"
         );
-      
-        // handle the result from the cross contract call this method is a callback for
-        match env::promise_result(0) {
-          PromiseResult::NotReady => unreachable!(),
-          PromiseResult::Failed => false,
-          PromiseResult::Successful(result) => {
-              let tokens = near_sdk::serde_json::from_slice::<Vec<Token>>(&result).unwrap();
-              if tokens.len() > 0 {
-                  true
-              } else {
-                  false
-              }
-          },
-        }
+        assert_eq!(self.draws.len(), draw_id, "Draws should be recorded in order");
+        self.draws.push(&DrawRecord {
+            seed: draw_seed.clone(),
+            block_height: env::block_index(),
+            prize_pot: 0,
+            payouts: vec![],
+        });
+        log!(
+            "Draw {}: winning tickets {:?} of {}",
+            draw_id, draw_seed.winning_tickets, draw_seed.tickets_count
+        );
+
+        let winners = draw_seed
+            .winning_tickets
+            .iter()
+            .map(|ticket| (*ticket, self.tickets.find_owner(*ticket)))
+            .collect();
+        (draw_id, winners)
     }
 
-    pub fn pay_nft_for_multiplier(&mut self, token: Token) -> Promise {
-        ext_nft::nft_transfer_call(
-            env::current_account_id(),
-            token.clone().token_id,
-            None,
-            None,
-            "Transfered".to_owned(),
-            &self.nft_contract_id,
-            0,
-            5_000_000_000_000
-        )
-        .then(ext_self::nft_transfer_callback(
-            token.token_id,
-            &env::current_account_id(), // this contract's account id
-            0, // yocto NEAR to attach to the callback
-            5_000_000_000_000 // gas to attach to the callback
-        ))
+    /// Splits the prize pot between the winners following the prize tiers, in the order the
+    /// tickets were drawn, and adds the prizes to the winners' pending prizes. The share of tiers
+    /// left without a winner stays in the pot.
+    pub(crate) fn award_prizes_to_winners(
+        &mut self,
+        draw_id: DrawId,
+        winners: Vec<(u64, AccountId)>,
+    ) -> Vec<PrizePayout> {
+        assert!(self.next_prize_event_epoch_height <= env::epoch_height(), "Next prize event time not reached");
+        self.internal_expire_prizes(draw_id);
+
+        let prize_pot = self.total_reward;
+        let mut payouts = Vec::with_capacity(winners.len());
+        for (tier, (ticket, account_id)) in winners.into_iter().enumerate() {
+            let amount = prize_pot * u128::from(self.prize_tiers[tier])
+                / u128::from(FULL_PRIZE_BASIS_POINTS);
+            if amount > 0 {
+                let mut account = self.internal_get_account(account_id.clone());
+                let expired_before_draw_id = self.prizes_expired_before_draw_id;
+                account
+                    .pending_prizes
+                    .retain(|prize| prize.draw_id >= expired_before_draw_id);
+                // The prizes of several tiers of one draw are one entry, so the account holds at
+                // most one per unexpired draw.
+                match account.pending_prizes.last_mut() {
+                    Some(prize) if prize.draw_id == draw_id => prize.amount += amount,
+                    _ => account.pending_prizes.push(PendingPrize { draw_id, amount }),
+                }
+                self.save_account(&account);
+
+                self.total_reward -= amount;
+                self.total_unclaimed_prizes += amount;
+                let unclaimed_prizes = self.unclaimed_prizes.get(&draw_id).unwrap_or(0);
+                self.unclaimed_prizes.insert(&draw_id, &(unclaimed_prizes + amount));
+            }
+            PoolEvent::WinnerSelected(vec![WinnerSelectedData {
+                draw_id: draw_id.into(),
+                tier: (tier as u64).into(),
+                ticket: ticket.into(),
+                account_id: account_id.clone(),
+                amount: amount.into(),
+            }])
+            .emit();
+            let mut account_wins = self.account_wins.get(&account_id).unwrap_or_default();
+            if account_wins.last() != Some(&draw_id) {
+                account_wins.push(draw_id);
+                self.account_wins.insert(&account_id, &account_wins);
+            }
+            payouts.push(PrizePayout {
+                draw_id,
+                tier: tier as u64,
+                ticket,
+                account_id,
+                amount,
+            });
+        }
+        let mut draw = self.draws.get(draw_id).expect("The draw isn't recorded");
+        draw.prize_pot = prize_pot;
+        draw.payouts = payouts.clone();
+        self.draws.replace(draw_id, &draw);
+        payouts
     }
 
-    pub fn nft_transfer_callback(&mut self, token_id: TokenId) -> bool {
-        assert_eq!(
-            env::promise_results_count(),
-            1,
-            "This is a callback method"
-        );
-      
-        // handle the result from the cross contract call this method is a callback for
-        match env::promise_result(0) {
-          PromiseResult::NotReady => unreachable!(),
-          PromiseResult::Failed => false,
-          PromiseResult::Successful(result) => {
-              let is_transfer_successful = near_sdk::serde_json::from_slice::<bool>(&result).unwrap();
-              if is_transfer_successful {          
-                    let mut account = self.get_account(env::predecessor_account_id());
-                    account.tickets_multiplier = 2;
-                    let current_staked_amount = account.staked_balance;
-
-                    self.internal_add_tickets(current_staked_amount, account.tickets_multiplier - 1);
-                    self.nft_hold.push(&token_id);
-              }
-              is_transfer_successful
-          },
+    /// Moves the prizes of the draws that are `NUM_DRAWS_TO_CLAIM_PRIZE` draws older than the
+    /// given one and are still unclaimed back into the prize pot.
+    pub(crate) fn internal_expire_prizes(&mut self, current_draw_id: DrawId) {
+        while self.prizes_expired_before_draw_id + NUM_DRAWS_TO_CLAIM_PRIZE <= current_draw_id {
+            let draw_id = self.prizes_expired_before_draw_id;
+            if let Some(amount) = self.unclaimed_prizes.remove(&draw_id) {
+                self.total_unclaimed_prizes -= amount;
+                self.total_reward += amount;
+                log!("Draw {}: {} of unclaimed prizes expired", draw_id, amount);
+            }
+            self.prizes_expired_before_draw_id += 1;
         }
     }
 
-    pub fn claim_reward_nft(&mut self) -> Promise {
-        let mut account = self.get_account(env::predecessor_account_id());
-        assert!(account.stake_points >= 10, "Not enough Stake Times to Claim NFT");
-        account.stake_points = 0;
-        let token_id = self.nft_hold.pop().unwrap();
+    /// Removes all the unexpired pending prizes of the account and returns their total amount.
+    pub(crate) fn internal_take_pending_prizes(&mut self, account_id: AccountId) -> Balance {
+        let mut account = self.internal_get_account(account_id);
+        let pending_prizes = std::mem::take(&mut account.pending_prizes);
+        self.save_account(&account);
 
-        ext_nft::nft_transfer_call(
-            env::predecessor_account_id(),
-            token_id.clone(),
-            None,
-            None,
-            "Transfered".to_owned(),
-            &self.nft_contract_id,
-            0,
-            5_000_000_000_000
-        )
-        // .then(ext_self::nft_transfer_callback(
-        //     token_id,
-        //     &env::current_account_id(), // this contract's account id
-        //     0, // yocto NEAR to attach to the callback
-        //     5_000_000_000_000 // gas to attach to the callback
-        // ))
+        let mut amount = 0;
+        for prize in pending_prizes {
+            if prize.draw_id < self.prizes_expired_before_draw_id {
+                // Already returned to the prize pot.
+                continue;
+            }
+            let unclaimed_prizes = self.unclaimed_prizes.get(&prize.draw_id).unwrap_or(0);
+            self.unclaimed_prizes.insert(&prize.draw_id, &(unclaimed_prizes - prize.amount));
+            amount += prize.amount;
+        }
+        self.total_unclaimed_prizes -= amount;
+        amount
     }
 }
 
@@ -466,11 +657,3 @@ trait NonFungibleToken {
     // view method
     fn nft_token(&self, token_id: String) -> Option<Token>;
 }
-
-#[ext_contract(ext_nft_enumeration)]
-trait NonFungibleTokenApprovalManagement: NonFungibleToken {
-    fn nft_total_supply(&self) -> U128;
-    fn nft_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Token>;
-    fn nft_supply_for_owner(&self, account_id: String) -> String;
-    fn nft_tokens_for_owner(&self, account_id: String, from_index: Option<U128>, limit: Option<u64>) -> Vec<Token>;
-}
\ No newline at end of file
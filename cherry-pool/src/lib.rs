@@ -1,20 +1,58 @@
 
 use near_sdk::{
     borsh::{self, BorshDeserialize, BorshSerialize},
-    collections::{LookupMap, Vector},
+    collections::{LookupMap, UnorderedMap, UnorderedSet, Vector},
     env, ext_contract,
     json_types::{ValidAccountId, U128, U64, Base58PublicKey},
     log, near_bindgen,
     serde::{Deserialize, Serialize},
-    wee_alloc, AccountId, Balance, EpochHeight, PanicOnDefault, 
+    wee_alloc, AccountId, Balance, BlockHeight, EpochHeight, PanicOnDefault, 
     Promise, PromiseOrValue,PromiseResult,
     PublicKey
 };
 use near_contract_standards::non_fungible_token::{Token, TokenId};
 // use std::convert::TryFrom;
 use uint::construct_uint;
+mod access_control;
+mod achievements;
+mod config;
+mod draw;
+mod events;
+mod fees;
 mod internal;
+mod loyalty;
+mod nft_boost;
+mod pause;
+mod randomness;
+mod serialize;
+mod share_token;
+mod storage;
+mod token_pool;
+mod upgrade;
+mod yield_source;
 mod test_utils;
+mod ticket_ledger;
+
+pub use crate::access_control::Role;
+pub use crate::achievements::{Achievement, AchievementStatus};
+pub use crate::config::PoolConfig;
+pub use crate::draw::{DrawRecord, PendingPrize, PrizePayout};
+pub use crate::events::PoolEvent;
+use crate::events::*;
+pub use crate::fees::{RewardFeeFraction, TreasuryFee};
+pub use crate::loyalty::{LoyaltyReward, LoyaltyRewardStatus, LoyaltyRules};
+pub use crate::nft_boost::{NftBoost, NftMultiplier, StakedNft};
+pub use crate::pause::PauseStatus;
+pub use crate::randomness::{DrawId, DrawSeed, PendingDraw};
+use crate::achievements::AccountAchievements;
+use crate::draw::{assert_valid_prize_tiers, FULL_PRIZE_BASIS_POINTS, NUM_DRAWS_TO_CLAIM_PRIZE};
+use crate::fees::{assert_valid_fees, split_reward};
+use crate::loyalty::{default_loyalty_rewards, LoyaltyAccount};
+use crate::nft_boost::{default_nft_multipliers, NO_BOOST_MULTIPLIER_BPS};
+use crate::ticket_ledger::TicketLedger;
+use crate::upgrade::{write_state_version, StakingPoolAccountV0};
+pub use crate::ticket_ledger::WinProbability;
+pub use crate::yield_source::{Delegation, YieldSource};
 
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
@@ -25,16 +63,27 @@ construct_uint! {
 }
 
 const STAKE_SHARE_PRICE_GUARANTEE_FUND: Balance = 1_000_000_000_000;
-const MIN_TICKET_DEPOSIT_PRICE: Balance = 10 * 10u128.pow(24);
-const POOL_THRESHOLD: Balance = 10_000 * 10u128.pow(24);
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct StakingPool {
     owner_id: AccountId,
+    /// The account the owner proposed to hand the ownership over to.
+    proposed_owner_id: Option<AccountId>,
+    operators: UnorderedSet<AccountId>,
+    guardians: UnorderedSet<AccountId>,
+    /// Draw schedule, ticket price and staking limits of the pool.
+    config: PoolConfig,
     accounts: LookupMap<String, StakingPoolAccount>,
-    tickets_count: u64,
-    tickets_random_slots: Vector<AccountId>,
+    /// Storage deposits of the accounts registered with `storage_deposit`.
+    storage_deposits: LookupMap<AccountId, Balance>,
+    /// The share of the epoch rewards restaked for the owner to cover the validator costs.
+    reward_fee_fraction: RewardFeeFraction,
+    /// The protocol treasury restaked its share of the epoch rewards, if any.
+    treasury: Option<TreasuryFee>,
+    /// Tickets of all the accounts, the prize winners are drawn from it weighted by the time the
+    /// tickets are held during the draw period.
+    tickets: TicketLedger,
     next_prize_event_epoch_height: EpochHeight,
     total_reward: Balance,
     total_staked_balance: Balance,
@@ -43,15 +92,75 @@ pub struct StakingPool {
     stake_public_key: PublicKey,
     last_epoch_height: EpochHeight,
     is_restake_paused: bool,
+    is_deposit_paused: bool,
+    is_draw_paused: bool,
+    /// In the emergency mode accounts can only unstake and withdraw.
+    is_emergency: bool,
+    /// The stake delegated to the external staking pool of the `YieldSource`.
+    delegation: Delegation,
     nft_contract_id: AccountId,
-    nft_hold: Vector<TokenId>
+    /// NFTs the pool held before the loyalty rewards, see `stock_held_reward_nfts`.
+    nft_hold: Vector<TokenId>,
+    /// The id the next prize draw will get.
+    next_draw_id: DrawId,
+    /// History of all the past draws, indexed by the draw id.
+    draws: Vector<DrawRecord>,
+    /// Ids of the draws each account has won a prize in.
+    account_wins: LookupMap<AccountId, Vec<DrawId>>,
+    /// Number of blocks between committing and revealing a draw. `None` draws inline in `ping`.
+    draw_reveal_delay: Option<BlockHeight>,
+    /// The committed draw waiting to be revealed. Tickets can't change while it's set.
+    pending_draw: Option<PendingDraw>,
+    /// The number of share transfers waiting for `ft_resolve_transfer`. No draw is committed
+    /// until they are resolved, since a resolved transfer can move tickets back.
+    pending_share_transfers: u32,
+    /// Share of the prize pot of every prize tier in basis points, the grand prize first.
+    /// Each draw picks one distinct ticket per tier.
+    prize_tiers: Vec<u16>,
+    /// Prizes awarded in a draw that are still waiting to be claimed.
+    unclaimed_prizes: LookupMap<DrawId, Balance>,
+    total_unclaimed_prizes: Balance,
+    /// Prizes of all the draws before this one have expired.
+    prizes_expired_before_draw_id: DrawId,
+    /// The account that deposited every boost NFT the pool holds.
+    nft_depositors: UnorderedMap<TokenId, AccountId>,
+    /// The tickets multipliers of the boost NFT rarities.
+    nft_multipliers: Vec<NftMultiplier>,
+    /// The NFT boosting the tickets of every boosted account.
+    nft_boosts: LookupMap<AccountId, NftBoost>,
+    /// The NFTs that failed to be returned while another NFT boosted the account.
+    unreturned_nfts: LookupMap<AccountId, Vec<TokenId>>,
+    /// How accounts earn loyalty points.
+    loyalty_rules: LoyaltyRules,
+    /// The NFT rarities accounts can spend their loyalty points on.
+    loyalty_rewards: Vec<LoyaltyReward>,
+    loyalty_accounts: LookupMap<AccountId, LoyaltyAccount>,
+    /// The NFTs left for the loyalty rewards of every rarity.
+    reward_nfts: LookupMap<String, Vec<TokenId>>,
+    /// The achievements of every account that ever deposited, kept after it's unregistered.
+    account_achievements: LookupMap<AccountId, AccountAchievements>,
+    /// The accounts of a pool deployed before the versioning that aren't converted yet.
+    legacy_accounts: Option<LookupMap<AccountId, StakingPoolAccountV0>>,
+    /// The ticket slots of a pool deployed before the versioning that aren't moved to the ledger
+    /// yet, see `migrate_legacy_tickets`.
+    legacy_ticket_slots: Option<Vector<AccountId>>,
 }
 
 /// staking pool interface that STAKE token contract depends on
 #[near_bindgen]
 impl StakingPool {
+    /// Initializes the pool with the given config, or the default weekly pool with 10 NEAR
+    /// tickets if it's `None`.
     #[init]
-    pub fn new(owner_id: AccountId, stake_public_key: Base58PublicKey) -> Self {
+    pub fn new(
+        owner_id: ValidAccountId,
+        stake_public_key: Base58PublicKey,
+        reward_fee_fraction: RewardFeeFraction,
+        config: Option<PoolConfig>,
+    ) -> Self {
+        let config = config.unwrap_or_default();
+        config.assert_valid();
+        assert_valid_fees(&reward_fee_fraction, &None);
         let account_balance = env::account_balance();
         let total_staked_balance = account_balance - STAKE_SHARE_PRICE_GUARANTEE_FUND;
         assert_eq!(
@@ -60,11 +169,17 @@ impl StakingPool {
             "The staking pool shouldn't be staking at the initialization"
         );
         let mut this = Self {
-            owner_id: owner_id,
+            owner_id: owner_id.into(),
+            proposed_owner_id: None,
+            operators: UnorderedSet::new(b"o".to_vec()),
+            guardians: UnorderedSet::new(b"g".to_vec()),
             accounts: LookupMap::new(b"a".to_vec()),
-            tickets_random_slots: Vector::new(b"t".to_vec()),
-            tickets_count: 0,
-            next_prize_event_epoch_height: env::epoch_height() + 14,
+            storage_deposits: LookupMap::new(b"s".to_vec()),
+            reward_fee_fraction,
+            treasury: None,
+            tickets: TicketLedger::new(b"t".to_vec(), env::epoch_height(), config.draw_period_epochs),
+            next_prize_event_epoch_height: env::epoch_height() + config.draw_period_epochs,
+            config,
             total_reward: 0,
             total_staked_balance: total_staked_balance,
             last_total_balance: account_balance,
@@ -72,113 +187,410 @@ impl StakingPool {
             stake_public_key: stake_public_key.into(),
             last_epoch_height: env::epoch_height(),
             is_restake_paused: false,
+            is_deposit_paused: false,
+            is_draw_paused: false,
+            is_emergency: false,
+            delegation: Delegation {
+                reserved_balance: total_staked_balance,
+                ..Default::default()
+            },
             nft_contract_id: "cherrypub_nft.testnet".to_owned(),
-            nft_hold: Vector::new(b"h".to_vec())
+            nft_hold: Vector::new(b"h".to_vec()),
+            next_draw_id: 0,
+            draws: Vector::new(b"d".to_vec()),
+            account_wins: LookupMap::new(b"w".to_vec()),
+            draw_reveal_delay: None,
+            pending_draw: None,
+            pending_share_transfers: 0,
+            prize_tiers: vec![FULL_PRIZE_BASIS_POINTS],
+            unclaimed_prizes: LookupMap::new(b"u".to_vec()),
+            total_unclaimed_prizes: 0,
+            prizes_expired_before_draw_id: 0,
+            nft_depositors: UnorderedMap::new(b"e".to_vec()),
+            nft_multipliers: default_nft_multipliers(),
+            nft_boosts: LookupMap::new(b"n".to_vec()),
+            unreturned_nfts: LookupMap::new(b"f".to_vec()),
+            loyalty_rules: LoyaltyRules::default(),
+            loyalty_rewards: default_loyalty_rewards(),
+            loyalty_accounts: LookupMap::new(b"l".to_vec()),
+            reward_nfts: LookupMap::new(b"r".to_vec()),
+            account_achievements: LookupMap::new(b"c".to_vec()),
+            legacy_accounts: None,
+            legacy_ticket_slots: None,
         };
+        write_state_version();
         this
     }
 
-    pub fn get_account(&self, account_id: AccountId) -> StakingPoolAccount {
-        let mut account = 
-            self.accounts
-            .get(&account_id)
-            .unwrap_or_else(|| StakingPoolAccount::new(&account_id));
-        account.staked_balance = 
-            self
-            .staked_amount_from_num_shares_rounded_down(account.stake_shares)
-            .into();
-        account.can_withdraw =
-            account.unstaked_available_epoch_height <= env::epoch_height();
-        account
+    /// Returns the human readable representation of the account for the given account ID.
+    pub fn get_account(&self, account_id: ValidAccountId) -> HumanReadableAccount {
+        let account = self.internal_get_account(account_id.into());
+        let tickets_multiplier_bps = self
+            .nft_boosts
+            .get(&account.account_id)
+            .map_or(NO_BOOST_MULTIPLIER_BPS, |nft_boost| nft_boost.multiplier_bps);
+        let loyalty_points = self.internal_loyalty_points(&account.account_id);
+        HumanReadableAccount {
+            account_id: account.account_id,
+            unstaked_balance: account.unstaked_balance.into(),
+            staked_balance: account.staked_balance.into(),
+            stake_shares: account.stake_shares.into(),
+            tickets_amount: account.tickets_amount.into(),
+            tickets_multiplier_bps,
+            loyalty_points: loyalty_points.into(),
+            can_withdraw: account.can_withdraw,
+            unstaked_available_epoch_height: account.unstaked_available_epoch_height.into(),
+        }
     }
 
     /// Distributes rewards and restakes if needed.
+    /// In commit-reveal mode also commits the prize draw once it's due.
     pub fn ping(&mut self) {
         if self.internal_ping() {
             self.internal_restake();
         }
+        if self.draw_reveal_delay.is_some() && self.is_prize_draw_due() {
+            self.internal_commit_draw();
+        }
+    }
+
+    /// Reveals the pending commit-reveal draw and pays its prizes to the winners.
+    /// Can be called by anyone once the reveal block height is reached.
+    pub fn reveal_draw(&mut self) -> Vec<PrizePayout> {
+        assert!(self.pending_draw.is_some(), "There is no pending draw to reveal");
+        if self.internal_ping() {
+            self.internal_restake();
+        }
+        self.internal_draw_prize()
+    }
+
+    /// Owner's method.
+    /// Switches prize draws to the commit-reveal mode with the given number of blocks between
+    /// commit and reveal, or back to inline draws with `None`.
+    pub fn set_draw_reveal_delay(&mut self, reveal_delay_blocks: Option<U64>) {
+        self.assert_owner();
+        let reveal_delay_blocks: Option<BlockHeight> = reveal_delay_blocks.map(|blocks| blocks.into());
+        assert!(self.pending_draw.is_none(), "Can't change the draw mode while a draw is pending");
+        if let Some(reveal_delay_blocks) = reveal_delay_blocks {
+            assert!(reveal_delay_blocks > 0, "The reveal delay should be positive");
+        }
+        self.draw_reveal_delay = reveal_delay_blocks;
+    }
+
+    /// Owner's method.
+    /// Updates the pool config. A new draw period applies from the next draw on. The ticket
+    /// price can only change while there are no tickets.
+    pub fn update_config(&mut self, config: PoolConfig) {
+        self.assert_owner();
+        config.assert_valid();
+        assert!(
+            config.ticket_price == self.config.ticket_price || self.tickets.total() == 0,
+            "The ticket price can't change while there are tickets"
+        );
+        assert_eq!(
+            config.deposit_token_id, self.config.deposit_token_id,
+            "The deposit token can't change"
+        );
+        assert_eq!(
+            config.yield_source, self.config.yield_source,
+            "The yield source can't change"
+        );
+        self.config = config;
+    }
+
+    /// Owner's method.
+    /// Updates the owner's share of the epoch rewards. Pings first so the current epoch's reward
+    /// is split with the old fee.
+    pub fn update_reward_fee_fraction(&mut self, reward_fee_fraction: RewardFeeFraction) {
+        self.assert_owner();
+        assert_valid_fees(&reward_fee_fraction, &self.treasury);
+        self.internal_ping();
+        self.reward_fee_fraction = reward_fee_fraction;
     }
 
+    /// Owner's method.
+    /// Sets the protocol treasury and its share of the epoch rewards, `None` stops the treasury
+    /// fee.
+    pub fn set_treasury(&mut self, treasury: Option<TreasuryFee>) {
+        self.assert_owner();
+        assert_valid_fees(&self.reward_fee_fraction, &treasury);
+        self.internal_ping();
+        self.treasury = treasury;
+    }
+
+    /// Owner's method.
+    /// Sets the prize structure as shares of the prize pot in basis points, the grand prize
+    /// first, e.g. `[5000, 1000, 1000, 1000, 1000, 1000]`. Shares may add up to less than the
+    /// whole pot, the rest rolls over to the next draw.
+    pub fn set_prize_tiers(&mut self, prize_tiers: Vec<u16>) {
+        self.assert_owner();
+        assert_valid_prize_tiers(&prize_tiers);
+        self.prize_tiers = prize_tiers;
+    }
+
+    /// Deposits the attached amount to the unstaked balance. The account has to be registered
+    /// with `storage_deposit` first.
     #[payable]
     pub fn deposit(&mut self) {
+        self.assert_native_pool();
+        self.assert_deposits_allowed();
         let need_to_restake = self.internal_ping();
         let amount = env::attached_deposit();
-        let mut account = self.get_account(env::predecessor_account_id());
+        let account_id = env::predecessor_account_id();
+        self.assert_registered(&account_id);
+        self.internal_record_deposit(&account_id, amount);
+        let mut account = self.internal_get_account(account_id);
         account.unstaked_balance += amount;
         self.last_total_balance += amount;
         self.save_account(&account);
+        PoolEvent::Deposit(vec![AmountData {
+            account_id: account.account_id,
+            amount: amount.into(),
+        }])
+        .emit();
 
         if need_to_restake {
             self.internal_restake();
         }
     }
 
-    pub fn stake(&mut self, amount: Balance) {
+    pub fn stake(&mut self, amount: U128) {
+        self.assert_not_emergency();
+        let amount: Balance = amount.into();
+        let account_id = env::predecessor_account_id();
         self.internal_ping();
-        self.internal_stake(amount);
-        self.internal_add_tickets(amount, 1);
+        self.internal_stake(&account_id, amount);
+        self.internal_add_tickets(&account_id, amount);
         self.internal_restake();
     }
 
     #[payable]
     pub fn deposit_and_stake(&mut self) {
         self.deposit();
-        self.stake(env::attached_deposit());
+        self.stake(env::attached_deposit().into());
     }
 
-    pub fn withdraw_all(&mut self) {
-        let mut account = self.get_account(env::predecessor_account_id());
-        assert!(account.can_withdraw, "account cannot withdraw yet");
-        assert!(account.unstaked_balance > 0, "unstaked balance is zero");
-        let unstaked_balance = account.unstaked_balance;
-        account.unstaked_balance = 0;
-        self.save_account(&account);
-        Promise::new(account.account_id).transfer(unstaked_balance);
+    /// Withdraws the given amount of the unstaked balance once it's unlocked.
+    pub fn withdraw(&mut self, amount: U128) -> WithdrawResult {
+        let need_to_restake = self.internal_ping();
+        let result = self.internal_withdraw(amount.into());
+        if need_to_restake {
+            self.internal_restake();
+        }
+        result
+    }
+
+    /// Withdraws the entire unstaked balance once it's unlocked.
+    pub fn withdraw_all(&mut self) -> WithdrawResult {
+        let need_to_restake = self.internal_ping();
+        let account = self.internal_get_account(env::predecessor_account_id());
+        assert!(account.unstaked_balance > 0, "Unstaked balance is zero");
+        let result = self.internal_withdraw(account.unstaked_balance);
+        if need_to_restake {
+            self.internal_restake();
+        }
+        result
     }
 
-    pub fn unstake(&mut self, amount: Balance) {
+    /// Claims all the prizes won by the predecessor that haven't expired yet.
+    /// With `restake` the prizes are staked right away and earn tickets, otherwise they are
+    /// transferred to the predecessor.
+    pub fn claim_prize(&mut self, restake: bool) -> U128 {
+        self.assert_not_emergency();
+        let need_to_restake = self.internal_ping();
+        let account_id = env::predecessor_account_id();
+        let amount = self.internal_take_pending_prizes(account_id.clone());
+        assert!(amount > 0, "There are no prizes to claim");
+
+        if restake {
+            let mut account = self.internal_get_account(account_id.clone());
+            account.unstaked_balance += amount;
+            self.save_account(&account);
+            self.internal_stake(&account_id, amount);
+            self.internal_add_tickets(&account_id, amount);
+            self.internal_restake();
+        } else {
+            assert!(
+                self.is_token_pool() || env::account_balance() >= amount,
+                "The prize pot is still being unstaked, try again later"
+            );
+            self.internal_send(account_id.clone(), amount);
+            if need_to_restake {
+                self.internal_restake();
+            }
+        }
+        PoolEvent::PrizePaid(vec![PrizePaidData {
+            account_id,
+            amount: amount.into(),
+            restaked: restake,
+        }])
+        .emit();
+        amount.into()
+    }
+
+    pub fn unstake(&mut self, amount: U128) {
+        let amount: Balance = amount.into();
         self.internal_ping();
         self.internal_unstake(amount);
         self.internal_remove_tickets(amount);
         self.internal_restake();
     }
 
-    pub fn unstake_all(&mut self) {
-        let mut account = self.get_account(env::predecessor_account_id());
+    /// Unstakes the entire staked balance and removes all the tickets of the predecessor. Its NFT
+    /// boost, if any, is sent back.
+    pub fn unstake_all(&mut self) -> UnstakeResult {
+        self.internal_ping();
+        let account = self.internal_get_account(env::predecessor_account_id());
         assert!(account.staked_balance > 0, "Staked balance is zero");
-        account.unstaked_balance += account.staked_balance;
-        self.total_staked_balance -= account.staked_balance;
-        account.staked_balance = 0;
-        self.save_account(&account);
+        let unstaked_amount = self.internal_unstake(account.staked_balance);
+        let tickets_removed = self.internal_remove_all_tickets();
+        self.internal_return_nft_boost(&account.account_id);
+        self.internal_restake();
+
+        let account = self.internal_get_account(account.account_id);
+        UnstakeResult {
+            account_id: account.account_id,
+            unstaked_amount: unstaked_amount.into(),
+            tickets_removed: tickets_removed.into(),
+            unstaked_available_epoch_height: account.unstaked_available_epoch_height.into(),
+        }
     }
 
     // *** View Method
     /// Returns the unstaked balance of the given account.
-    pub fn get_account_unstaked_balance(&self, account_id: AccountId) -> u128 {
-        self.get_account(account_id).unstaked_balance
+    pub fn get_account_unstaked_balance(&self, account_id: ValidAccountId) -> U128 {
+        self.internal_get_account(account_id.into()).unstaked_balance.into()
     }
 
     /// Returns the staked balance of the given account.
     /// NOTE: This is computed from the amount of "stake" shares the given account has and the
     /// current amount of total staked balance and total stake shares on the account.
-    pub fn get_account_staked_balance(&self, account_id: AccountId) -> u128 {
-        self.get_account(account_id).staked_balance
+    pub fn get_account_staked_balance(&self, account_id: ValidAccountId) -> U128 {
+        self.internal_get_account(account_id.into()).staked_balance.into()
     }
 
     /// current numbers of tickets on the account
-    pub fn get_account_tickets_amount(&self, account_id: AccountId) -> u64 {
-        self.get_account(account_id).tickets_amount
+    pub fn get_account_tickets_amount(&self, account_id: ValidAccountId) -> U64 {
+        self.internal_get_account(account_id.into()).tickets_amount.into()
+    }
+
+    /// Returns the total number of tickets in the pool.
+    pub fn get_total_tickets(&self) -> U64 {
+        self.tickets.total().into()
+    }
+
+    /// Returns the odds of the given account to win the next draw, based on its time-weighted
+    /// average tickets over the current draw period.
+    pub fn get_account_win_probability(&self, account_id: ValidAccountId) -> WinProbability {
+        self.tickets.win_probability(account_id.as_ref())
     }
 
     /// Returns the total balance of the given account (including staked and unstaked balances).
-    pub fn get_account_total_balance(&self, account_id: AccountId) -> u128 {
-        let account = self.get_account(account_id);
+    pub fn get_account_total_balance(&self, account_id: ValidAccountId) -> U128 {
+        let account = self.internal_get_account(account_id.into());
         (account.unstaked_balance + account.staked_balance).into()
     }
 
     /// Returns `true` if the given account can withdraw tokens in the current epoch.
-    pub fn is_account_unstaked_balance_available(&self, account_id: AccountId) -> bool {
-        self.get_account(account_id).can_withdraw
+    pub fn is_account_unstaked_balance_available(&self, account_id: ValidAccountId) -> bool {
+        self.internal_get_account(account_id.into()).can_withdraw
+    }
+
+    /// Returns the randomness inputs and the seed commitment recorded for the given draw.
+    pub fn get_draw_seed(&self, draw_id: U64) -> Option<DrawSeed> {
+        self.draws.get(draw_id.into()).map(|draw| draw.seed)
+    }
+
+    /// Returns the given draw from the draw history.
+    pub fn get_draw(&self, draw_id: U64) -> Option<DrawRecord> {
+        self.draws.get(draw_id.into())
+    }
+
+    /// Returns the number of draws in the draw history.
+    pub fn get_number_of_draws(&self) -> U64 {
+        self.draws.len().into()
+    }
+
+    /// Returns the list of past draws from the given index, oldest first.
+    pub fn get_draws(&self, from_index: U64, limit: U64) -> Vec<DrawRecord> {
+        let from_index: u64 = from_index.into();
+        let limit: u64 = limit.into();
+        (from_index..std::cmp::min(from_index + limit, self.draws.len()))
+            .map(|draw_id| self.draws.get(draw_id).unwrap())
+            .collect()
+    }
+
+    /// Returns every prize the given account has won, oldest first.
+    pub fn get_account_wins(&self, account_id: ValidAccountId) -> Vec<PrizePayout> {
+        let account_id: AccountId = account_id.into();
+        self.account_wins
+            .get(&account_id)
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|draw_id| self.draws.get(draw_id).unwrap().payouts)
+            .filter(|payout| payout.account_id == account_id)
+            .collect()
+    }
+
+    /// Returns the committed draw waiting to be revealed, if any.
+    pub fn get_pending_draw(&self) -> Option<PendingDraw> {
+        self.pending_draw.clone()
+    }
+
+    /// Recomputes the winning tickets of the given draw from its recorded randomness inputs.
+    /// Panics if the inputs don't match the recorded seed commitment.
+    pub fn compute_winning_tickets(&self, draw_id: U64) -> Option<Vec<U64>> {
+        self.draws.get(draw_id.into()).map(|draw| {
+            draw.seed
+                .compute_winning_tickets()
+                .into_iter()
+                .map(|ticket| ticket.into())
+                .collect()
+        })
+    }
+
+    /// Returns the prizes the given account can claim.
+    pub fn get_account_pending_prizes(&self, account_id: ValidAccountId) -> Vec<PendingPrize> {
+        self.internal_get_account(account_id.into())
+            .pending_prizes
+            .into_iter()
+            .filter(|prize| prize.draw_id >= self.prizes_expired_before_draw_id)
+            .collect()
+    }
+
+    /// Returns the amount that will be split between the winners of the next draw.
+    pub fn get_prize_pot(&self) -> U128 {
+        self.total_reward.into()
+    }
+
+    /// Returns the number of draws a prize stays claimable for.
+    pub fn get_num_draws_to_claim_prize(&self) -> U64 {
+        NUM_DRAWS_TO_CLAIM_PRIZE.into()
+    }
+
+    /// Returns the pool config.
+    pub fn get_config(&self) -> PoolConfig {
+        self.config.clone()
+    }
+
+    /// Returns the owner's share of the epoch rewards.
+    pub fn get_reward_fee_fraction(&self) -> RewardFeeFraction {
+        self.reward_fee_fraction.clone()
+    }
+
+    /// Returns the protocol treasury and its share of the epoch rewards.
+    pub fn get_treasury(&self) -> Option<TreasuryFee> {
+        self.treasury.clone()
+    }
+
+    /// Returns the stake delegated to the external staking pool of the yield source.
+    pub fn get_delegation(&self) -> Delegation {
+        self.delegation.clone()
+    }
+
+    /// Returns the prize tier shares in basis points.
+    pub fn get_prize_tiers(&self) -> Vec<u16> {
+        self.prize_tiers.clone()
     }
 
     pub fn on_stake_action(&mut self) {
@@ -214,7 +626,31 @@ impl StakingPool {
 }
 
 impl StakingPool {
+    /// Returns the account with its staked balance and withdrawal availability computed, or a new
+    /// empty account if it doesn't exist.
+    pub(crate) fn internal_get_account(&self, account_id: AccountId) -> StakingPoolAccount {
+        let mut account = 
+            self.accounts
+            .get(&account_id)
+            .or_else(|| self.internal_legacy_account(&account_id))
+            .unwrap_or_else(|| StakingPoolAccount::new(&account_id));
+        account.staked_balance = 
+            self
+            .staked_amount_from_num_shares_rounded_down(account.stake_shares)
+            .into();
+        account.can_withdraw =
+            account.unstaked_available_epoch_height <= env::epoch_height();
+        account
+    }
+
     fn save_account(&mut self, account: &StakingPoolAccount) {
+        assert!(
+            self.legacy_ticket_slots.is_none(),
+            "The legacy tickets are being migrated"
+        );
+        if let Some(legacy_accounts) = self.legacy_accounts.as_mut() {
+            legacy_accounts.remove(&account.account_id);
+        }
         self.accounts.insert(&account.account_id, account);
     }
 }
@@ -228,8 +664,13 @@ pub trait SelfContract {
     /// follow withdraw calls might fail. To mitigate this, the contract will issue a new unstaking
     /// action in case of the failure of the first staking action.
     fn on_stake_action(&mut self);
-    fn nft_valid_callback(&self) -> bool;
-    fn nft_transfer_callback(&mut self, token_id: TokenId) -> bool;
+    fn ft_resolve_transfer(&mut self, sender_id: AccountId, receiver_id: AccountId, amount: U128) -> U128;
+    fn on_token_sent(&mut self, account_id: AccountId, amount: U128);
+    fn on_nft_boost_token(&mut self, account_id: AccountId, token_id: TokenId) -> bool;
+    fn on_nft_boost_returned(&mut self, account_id: AccountId, nft_boost: NftBoost);
+    fn on_unreturned_nft_sent(&mut self, account_id: AccountId, token_id: TokenId);
+    fn on_loyalty_reward_sent(&mut self, account_id: AccountId, rarity: String, token_id: TokenId, points: U64);
+    fn on_achievement_nft_minted(&mut self, account_id: AccountId, achievement: Achievement, deposit: U128);
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
@@ -237,20 +678,73 @@ pub trait SelfContract {
 pub struct StakingPoolAccount {
     pub account_id: AccountId,
     /// The unstaked balance that can be withdrawn or staked.
+    #[serde(with = "crate::serialize::u128_dec_format")]
     pub unstaked_balance: Balance,
     /// The amount balance staked at the current "stake" share price.
+    #[serde(with = "crate::serialize::u128_dec_format")]
     pub staked_balance: Balance,
     /// The amount of shares
+    #[serde(with = "crate::serialize::u128_dec_format")]
     pub stake_shares: Balance,
-    /// Updated when stake/unstake actions happen, used for reward
+    /// The points of the accounts from before the loyalty program, they carry over to their
+    /// loyalty points.
+    #[serde(with = "crate::serialize::u64_dec_format")]
     pub stake_points: u64,
     /// The amount of tickets
+    #[serde(with = "crate::serialize::u64_dec_format")]
     pub tickets_amount: u64,
-    /// Bonus multiplier by using special NFT
+    /// The multiplier of the accounts boosted before the NFT boosts were kept in `nft_boosts`, no
+    /// longer used.
+    #[serde(with = "crate::serialize::u64_dec_format")]
     pub tickets_multiplier: u64,
     /// Whether the unstaked balance is available for withdrawal now.
     pub can_withdraw: bool,
+    #[serde(with = "crate::serialize::u64_dec_format")]
     pub unstaked_available_epoch_height: EpochHeight,
+    /// Prizes won and not claimed yet.
+    pub pending_prizes: Vec<PendingPrize>,
+}
+
+/// Represents an account structure readable by humans.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct HumanReadableAccount {
+    pub account_id: AccountId,
+    /// The unstaked balance that can be withdrawn or staked.
+    pub unstaked_balance: U128,
+    /// The amount balance staked at the current "stake" share price.
+    pub staked_balance: U128,
+    pub stake_shares: U128,
+    pub tickets_amount: U64,
+    /// The multiplier of the NFT boost in basis points, `10_000` without a boost.
+    pub tickets_multiplier_bps: u32,
+    pub loyalty_points: U64,
+    /// Whether the unstaked balance is available for withdrawal now.
+    pub can_withdraw: bool,
+    pub unstaked_available_epoch_height: U64,
+}
+
+/// The outcome of unstaking the whole staked balance.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UnstakeResult {
+    pub account_id: AccountId,
+    /// The amount moved to the unstaked balance.
+    pub unstaked_amount: U128,
+    pub tickets_removed: U64,
+    /// The epoch from which the unstaked balance can be withdrawn.
+    pub unstaked_available_epoch_height: U64,
+}
+
+/// The outcome of a withdrawal.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawResult {
+    pub account_id: AccountId,
+    /// The amount transferred to the account.
+    pub amount: U128,
+    /// The unstaked balance left after the withdrawal.
+    pub unstaked_balance: U128,
 }
 
 impl StakingPoolAccount {
@@ -264,24 +758,34 @@ impl StakingPoolAccount {
             tickets_multiplier: 1,
             tickets_amount: 0,
             can_withdraw: false,
-            unstaked_available_epoch_height: 0
+            unstaked_available_epoch_height: 0,
+            pending_prizes: vec![],
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::convert::TryFrom;
+    use std::convert::{TryFrom, TryInto};
 
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_contract_standards::non_fungible_token::core::NonFungibleTokenReceiver;
+    use near_contract_standards::storage_management::StorageManagement;
     use near_sdk::{serde_json, testing_env, MockedBlockchain, VMContext};
 
     use crate::test_utils::*;
+    use crate::achievements::ACHIEVEMENT_NFT_STORAGE_DEPOSIT;
+    use crate::storage::{ACHIEVEMENTS_STORAGE_USAGE, STORAGE_PRICE_PER_BYTE};
+    use crate::upgrade::{STATE_VERSION, STATE_VERSION_KEY};
 
     use super::*;
 
     struct Emulator {
         pub contract: StakingPool,
         pub epoch_height: EpochHeight,
+        pub block_index: BlockHeight,
         pub amount: Balance,
         pub locked_amount: Balance,
         last_total_staked_balance: Balance,
@@ -293,6 +797,16 @@ mod tests {
         pub fn new(
             owner: String,
             stake_public_key: String,
+            reward_fee_fraction: RewardFeeFraction,
+        ) -> Self {
+            Self::new_with_config(owner, stake_public_key, reward_fee_fraction, None)
+        }
+
+        pub fn new_with_config(
+            owner: String,
+            stake_public_key: String,
+            reward_fee_fraction: RewardFeeFraction,
+            config: Option<PoolConfig>,
         ) -> Self {
             let context = VMContextBuilder::new()
                 .current_account_id(owner.clone())
@@ -300,20 +814,46 @@ mod tests {
                 .finish();
             testing_env!(context.clone());
             let contract = StakingPool::new(
-                owner,
+                ValidAccountId::try_from(owner).unwrap(),
                 Base58PublicKey::try_from(stake_public_key).unwrap(),
+                reward_fee_fraction,
+                config,
             );
             let last_total_staked_balance = contract.total_staked_balance;
             let last_total_stake_shares = contract.total_stake_shares;
-            Emulator {
+            let mut emulator = Emulator {
                 contract,
                 epoch_height: 0,
+                block_index: 0,
                 amount: ntoy(30),
                 locked_amount: 0,
                 last_total_staked_balance,
                 last_total_stake_shares,
                 context,
-            }
+            };
+            emulator.register(alice());
+            emulator.register(bob());
+            emulator
+        }
+
+        pub fn register(&mut self, account_id: AccountId) {
+            let storage_cost = self.contract.account_storage_cost();
+            self.update_context(account_id, storage_cost);
+            self.contract.storage_deposit(None, None);
+            self.amount += storage_cost;
+        }
+
+        pub fn deposit(&mut self, account_id: AccountId, amount: Balance) {
+            self.update_context(account_id, amount);
+            self.contract.deposit();
+            self.amount += amount;
+        }
+
+        pub fn deposit_and_stake(&mut self, account_id: AccountId, amount: Balance) {
+            self.deposit(account_id.clone(), amount);
+            self.update_context(account_id, 0);
+            self.contract.stake(amount.into());
+            self.simulate_stake_call();
         }
 
         fn verify_stake_price_increase_guarantee(&mut self) {
@@ -332,6 +872,7 @@ mod tests {
 
         pub fn update_context(&mut self, predecessor_account_id: String, deposit: Balance) {
             self.verify_stake_price_increase_guarantee();
+            self.contract.assert_tickets_invariant();
             self.context = VMContextBuilder::new()
                 .current_account_id(staking())
                 .predecessor_account_id(predecessor_account_id.clone())
@@ -340,12 +881,9 @@ mod tests {
                 .account_balance(self.amount)
                 .account_locked_balance(self.locked_amount)
                 .epoch_height(self.epoch_height)
+                .block_index(self.block_index)
                 .finish();
             testing_env!(self.context.clone());
-            println!(
-                "Epoch: {}, Deposit: {}, amount: {}, locked_amount: {}",
-                self.epoch_height, deposit, self.amount, self.locked_amount
-            );
         }
 
         pub fn simulate_stake_call(&mut self) {
@@ -359,17 +897,28 @@ mod tests {
 
         pub fn skip_epochs(&mut self, num: EpochHeight) {
             self.epoch_height += num;
+            self.block_index += num * 100;
             self.locked_amount = (self.locked_amount * (100 + u128::from(num))) / 100;
         }
     }
 
+    /// Returns an emulator of a pool without fees, where only alice and bob are registered.
+    fn emulator() -> Emulator {
+        Emulator::new(owner(), "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_owned(), zero_fee())
+    }
+
+    /// Returns an emulator where the given accounts have deposited and staked the given amounts.
+    fn staked_emulator(accounts: &[AccountId], amounts: &[Balance]) -> Emulator {
+        let mut emulator = emulator();
+        for (account_id, amount) in accounts.iter().zip(amounts) {
+            emulator.deposit_and_stake(account_id.clone(), *amount);
+        }
+        emulator
+    }
+
     #[test]
     fn test_restake_fail() {
-        let mut emulator = Emulator::new(
-            owner(),
-            "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_string(),
-            // zero_fee(),
-        );
+        let mut emulator = emulator();
         emulator.update_context(bob(), 0);
         emulator.contract.internal_restake();
         // let receipts = env::created_receipts();
@@ -395,20 +944,10 @@ mod tests {
 
     #[test]
     fn test_stake_unstake() {
-        let mut emulator = Emulator::new(
-            owner(),
-            "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_string(),
-            // zero_fee(),
-        );
         let deposit_amount = ntoy(10_000);
-        emulator.update_context(bob(), deposit_amount);
-        emulator.contract.deposit();
-        emulator.amount += deposit_amount;
-        emulator.update_context(bob(), 0);
-        emulator.contract.stake(deposit_amount.into());
-        emulator.simulate_stake_call();
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
         assert_eq!(
-            emulator.contract.get_account_staked_balance(bob()),
+            emulator.contract.get_account_staked_balance(bob().try_into().unwrap()).0,
             deposit_amount
         );
         let locked_amount = emulator.locked_amount;
@@ -418,120 +957,1798 @@ mod tests {
         emulator.locked_amount = locked_amount + ntoy(10);
         emulator.update_context(bob(), 0);
         emulator.contract.ping();
-        println!("Total Stake shares: {}", emulator.contract.total_stake_shares);
-        println!("Total Stake Balance: {}", emulator.contract.total_staked_balance);
+        // The reward goes to the prize pot, the staked balance doesn't grow.
         assert_eq_in_near!(
-            emulator.contract.get_account_staked_balance(bob()),
-            deposit_amount + ntoy(10)
+            emulator.contract.get_account_staked_balance(bob().try_into().unwrap()).0,
+            deposit_amount
         );
+        assert_eq_in_near!(emulator.contract.get_prize_pot().0, ntoy(10));
         emulator.contract.unstake((deposit_amount / 2).into());
         emulator.simulate_stake_call();
         assert_eq_in_near!(
-            emulator.contract.get_account_staked_balance(bob()),
-            deposit_amount / 2 + ntoy(10)
+            emulator.contract.get_account_staked_balance(bob().try_into().unwrap()).0,
+            deposit_amount / 2
         );
         assert_eq_in_near!(
-            emulator.contract.get_account_unstaked_balance(bob()),
+            emulator.contract.get_account_unstaked_balance(bob().try_into().unwrap()).0,
             deposit_amount / 2
         );
-        let acc = emulator.contract.get_account(bob());
+        let acc = emulator.contract.get_account(bob().try_into().unwrap());
         assert_eq!(acc.account_id, bob());
-        assert_eq_in_near!(acc.unstaked_balance, deposit_amount / 2);
-        assert_eq_in_near!(acc.staked_balance, deposit_amount / 2 + ntoy(10));
+        assert_eq_in_near!(acc.unstaked_balance.0, deposit_amount / 2);
+        assert_eq_in_near!(acc.staked_balance.0, deposit_amount / 2);
         assert!(!acc.can_withdraw);
 
         assert!(!emulator
             .contract
-            .is_account_unstaked_balance_available(bob()),);
+            .is_account_unstaked_balance_available(bob().try_into().unwrap()),);
         emulator.skip_epochs(4);
         emulator.update_context(bob(), 0);
         assert!(emulator
             .contract
-            .is_account_unstaked_balance_available(bob()),);
+            .is_account_unstaked_balance_available(bob().try_into().unwrap()),);
     }
 
     #[test]
-    fn test_add_remove_tickets() {
-        let mut emulator = Emulator::new(owner(), "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_owned());
+    fn test_reward_fees() {
+        let mut emulator = Emulator::new(
+            owner(),
+            "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_string(),
+            RewardFeeFraction {
+                numerator: 10,
+                denominator: 100,
+            },
+        );
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_treasury(Some(TreasuryFee {
+            account_id: alice(),
+            fee_fraction: RewardFeeFraction {
+                numerator: 5,
+                denominator: 100,
+            },
+        }));
 
         let deposit_amount = ntoy(1_000);
-        emulator.update_context(bob(), deposit_amount);
-        emulator.contract.deposit();
-        emulator.amount += deposit_amount;
-        emulator.update_context(bob(), 0);
+        emulator.deposit_and_stake(bob(), deposit_amount);
 
-        emulator.contract.stake(deposit_amount.into());
+        emulator.skip_epochs(1);
+        emulator.locked_amount = emulator.contract.total_staked_balance + ntoy(100);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
         emulator.simulate_stake_call();
+
+        assert_eq_in_near!(emulator.contract.get_prize_pot().0, ntoy(85));
+        assert_eq_in_near!(emulator.contract.get_account_staked_balance(owner().try_into().unwrap()).0, ntoy(10));
+        assert_eq_in_near!(emulator.contract.get_account_staked_balance(alice().try_into().unwrap()).0, ntoy(5));
+        assert_eq_in_near!(emulator.contract.get_account_staked_balance(bob().try_into().unwrap()).0, deposit_amount);
+        // The unregistered owner paid its storage deposit from the fee.
         assert_eq!(
-            emulator.contract.get_account_tickets_amount(bob()),
-            1_00
+            emulator.contract.storage_balance_of(owner().try_into().unwrap()).unwrap().total.0,
+            emulator.contract.account_storage_cost()
         );
+        // Fee shares don't come with tickets.
+        assert_eq!(emulator.contract.get_account_tickets_amount(owner().try_into().unwrap()).0, 0);
+        assert_eq!(emulator.contract.get_account_tickets_amount(alice().try_into().unwrap()).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The reward fees can't take more than 20/100 of the rewards")]
+    fn test_reward_fees_over_max() {
+        let mut emulator = Emulator::new(
+            owner(),
+            "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_string(),
+            RewardFeeFraction {
+                numerator: 1,
+                denominator: 10,
+            },
+        );
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_treasury(Some(TreasuryFee {
+            account_id: alice(),
+            fee_fraction: RewardFeeFraction {
+                numerator: 11,
+                denominator: 100,
+            },
+        }));
+    }
+
+    #[test]
+    fn test_unstake_all_and_withdraw() {
+        let deposit_amount = ntoy(1_000);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+        let total_staked_balance = emulator.contract.total_staked_balance;
+        let total_stake_shares = emulator.contract.total_stake_shares;
+
+        emulator.skip_epochs(1);
+        emulator.update_context(bob(), 0);
+        let result = emulator.contract.unstake_all();
+        emulator.simulate_stake_call();
+        assert_eq!(result.account_id, bob());
+        assert_eq_in_near!(result.unstaked_amount.0, deposit_amount);
+        assert_eq!(result.tickets_removed.0, 1_00);
+        assert_eq!(result.unstaked_available_epoch_height.0, 5);
+
+        let acc = emulator.contract.get_account(bob().try_into().unwrap());
+        assert_eq!(acc.stake_shares.0, 0);
+        assert_eq!(acc.staked_balance.0, 0);
+        assert_eq!(acc.tickets_amount.0, 0);
+        assert_eq_in_near!(acc.unstaked_balance.0, deposit_amount);
+        assert_eq!(emulator.contract.get_total_tickets().0, 0);
+        assert!(emulator.contract.total_stake_shares < total_stake_shares);
+        assert!(emulator.contract.total_staked_balance < total_staked_balance);
 
         emulator.skip_epochs(4);
         emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        let last_total_balance = emulator.contract.last_total_balance;
+        let result = emulator.contract.withdraw(ntoy(400).into());
+        assert_eq!(result.amount.0, ntoy(400));
+        assert_eq_in_near!(result.unstaked_balance.0, ntoy(600));
+        assert_eq!(emulator.contract.last_total_balance, last_total_balance - ntoy(400));
+        emulator.amount -= ntoy(400);
+
+        emulator.update_context(bob(), 0);
+        let result = emulator.contract.withdraw_all();
+        assert_eq_in_near!(result.amount.0, ntoy(600));
+        assert_eq!(result.unstaked_balance.0, 0);
+        assert_eq!(emulator.contract.get_account_unstaked_balance(bob().try_into().unwrap()).0, 0);
+        // The empty account is removed and its storage deposit, but the ticket slot, is refunded.
+        assert!(emulator.contract.storage_balance_of(ValidAccountId::try_from(bob()).unwrap()).is_none());
+    }
 
-        emulator.contract.unstake((deposit_amount / 2).into());
-        emulator.simulate_stake_call();
+    #[test]
+    #[should_panic(expected = "The account carol is not registered")]
+    fn test_deposit_unregistered_account() {
+        let mut emulator = emulator();
+        emulator.update_context("carol".to_string(), ntoy(1_00));
+        emulator.contract.deposit();
+    }
+
+    #[test]
+    fn test_storage_registration() {
+        let mut emulator = emulator();
+        let storage_cost = emulator.contract.storage_balance_bounds().min.0;
         assert_eq!(
-            emulator.contract.get_account_tickets_amount(bob()),
-            50
+            emulator.contract.storage_balance_of(ValidAccountId::try_from(bob()).unwrap()).unwrap().total.0,
+            storage_cost
         );
+
+        let last_total_balance = emulator.contract.last_total_balance;
+        emulator.register("carol".to_string());
+        assert_eq!(emulator.contract.last_total_balance, last_total_balance + storage_cost);
+
+        emulator.update_context("carol".to_string(), 1);
+        assert!(emulator.contract.storage_unregister(None));
+        assert_eq!(emulator.contract.last_total_balance, last_total_balance);
+        assert!(emulator.contract.storage_balance_of(ValidAccountId::try_from("carol".to_string()).unwrap()).is_none());
     }
 
     #[test]
-    fn test_winner_announcement() {
-        let mut emulator = Emulator::new(owner(), "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_owned());
-
+    fn test_storage_registration_again() {
         let deposit_amount = ntoy(1_00);
-        emulator.update_context(bob(), deposit_amount);
-        emulator.contract.deposit();
-        emulator.amount += deposit_amount;
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
         emulator.update_context(bob(), 0);
-
-        emulator.contract.stake(deposit_amount.into());
+        emulator.contract.unstake_all();
         emulator.simulate_stake_call();
+        emulator.skip_epochs(4);
+        emulator.update_context(bob(), 0);
+        emulator.contract.withdraw_all();
+
+        // The emptied account is unregistered and its ticket slot released, only the achievements
+        // record stays paid for.
+        assert!(emulator.contract.storage_balance_of(bob().try_into().unwrap()).is_none());
+        assert!(!emulator.contract.tickets.has_slot(&bob()));
+        let storage_cost = emulator.contract.account_storage_cost();
+        let achievements_storage_cost = Balance::from(ACHIEVEMENTS_STORAGE_USAGE) * STORAGE_PRICE_PER_BYTE;
+        let last_total_balance = emulator.contract.last_total_balance;
+        emulator.update_context(bob(), storage_cost - achievements_storage_cost);
+        emulator.contract.storage_deposit(None, None);
         assert_eq!(
-            emulator.contract.get_account_tickets_amount(bob()),
-            10
+            emulator.contract.last_total_balance,
+            last_total_balance + storage_cost - achievements_storage_cost
         );
+        assert_eq!(
+            emulator.contract.storage_balance_of(bob().try_into().unwrap()).unwrap().total.0,
+            storage_cost
+        );
+    }
 
-        emulator.skip_epochs(14);
+    #[test]
+    #[should_panic(expected = "The unstaked balance is not yet available due to unstaking delay")]
+    fn test_withdraw_too_early() {
+        let deposit_amount = ntoy(1_000);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.unstake_all();
+        emulator.simulate_stake_call();
+        emulator.skip_epochs(3);
         emulator.update_context(bob(), 0);
+        emulator.contract.withdraw(ntoy(1).into());
+    }
+
+    #[test]
+    fn test_share_transfer_moves_tickets() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
 
-        let winner = emulator.contract.get_prize_winner();
-        assert_eq!(winner, bob());
+        assert!(emulator.contract.storage_balance_of(ValidAccountId::try_from(alice()).unwrap()).is_some());
 
-        let contract_balance = emulator.amount;
-        let reward = emulator.contract.total_reward;
-        let promise = emulator.contract.transfer_prize_to_winner(winner);
+        let shares = emulator.contract.ft_balance_of(ValidAccountId::try_from(bob()).unwrap()).0;
+        emulator.update_context(bob(), 1);
+        emulator.contract.ft_transfer(ValidAccountId::try_from(alice()).unwrap(), (shares / 2).into(), None);
+        emulator.amount += 1;
+
+        assert_eq!(emulator.contract.ft_balance_of(ValidAccountId::try_from(alice()).unwrap()).0, shares / 2);
+        assert_eq!(emulator.contract.ft_balance_of(ValidAccountId::try_from(bob()).unwrap()).0, shares - shares / 2);
+        assert_eq_in_near!(emulator.contract.get_account_staked_balance(alice().try_into().unwrap()).0, deposit_amount / 2);
+        assert_eq!(emulator.contract.get_account_tickets_amount(alice().try_into().unwrap()).0, 5);
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 5);
+        assert_eq!(emulator.contract.get_total_tickets().0, 10);
+        assert_eq!(emulator.contract.tickets.tickets_of(&alice()), 5);
+    }
+
+    #[test]
+    fn test_share_transfer_call_holds_draw() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_draw_reveal_delay(Some(5.into()));
 
+        let shares = emulator.contract.ft_balance_of(ValidAccountId::try_from(bob()).unwrap()).0;
+        emulator.update_context(bob(), 1);
+        emulator.contract.ft_transfer_call(ValidAccountId::try_from(alice()).unwrap(), shares.into(), None, "".to_string());
+        emulator.amount += 1;
+
+        // No draw is committed until the unused shares and their tickets are back.
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        assert!(emulator.contract.get_pending_draw().is_none());
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(
+            emulator.context.clone(),
+            PromiseResult::Successful(serde_json::to_vec(&U128(shares)).unwrap()),
+        );
+        emulator.contract.ft_resolve_transfer(bob().try_into().unwrap(), alice().try_into().unwrap(), shares.into());
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 10);
         emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        assert_eq!(emulator.contract.get_pending_draw().unwrap().tickets_count, 140);
+    }
 
-        println!("{}", emulator.contract.total_staked_balance);
-        assert_eq!(emulator.amount, contract_balance - reward);
+    #[test]
+    #[should_panic(expected = "The account carol is not registered")]
+    fn test_share_transfer_to_unregistered_account() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+
+        emulator.update_context(bob(), 1);
+        emulator.contract.ft_transfer(ValidAccountId::try_from("carol".to_string()).unwrap(), ntoy(1).into(), None);
     }
 
-    // #[test]
-    fn test_rewards() {
-        let mut emulator = Emulator::new(
+    fn token_pool_emulator() -> Emulator {
+        let config = PoolConfig {
+            deposit_token_id: Some("usn".to_string()),
+            yield_source: YieldSource::TokenTransfers {
+                account_id: "strategy".to_string(),
+            },
+            ..Default::default()
+        };
+        Emulator::new_with_config(
             owner(),
-            "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_string()
+            "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_owned(),
+            zero_fee(),
+            Some(config),
+        )
+    }
+
+    #[test]
+    fn test_token_deposits() {
+        let mut emulator = token_pool_emulator();
+        let deposit_amount = ntoy(1_00);
+        emulator.update_context("usn".to_string(), 0);
+        emulator.contract.ft_on_transfer(
+            ValidAccountId::try_from(bob()).unwrap(),
+            deposit_amount.into(),
+            "deposit_and_stake".to_string(),
         );
-        let initial_balance = ntoy(100);
-        emulator.update_context(alice(), initial_balance);
-        emulator.contract.deposit();
-        emulator.amount += initial_balance;
-        let mut remaining = 100;
-        let mut amount = 1;
-        while remaining >= 4 {
-            emulator.skip_epochs(3);
-            emulator.update_context(alice(), 0);
+        assert_eq!(emulator.contract.get_account_staked_balance(bob().try_into().unwrap()).0, deposit_amount);
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 10);
+
+        emulator.update_context("usn".to_string(), 0);
+        emulator.contract.ft_on_transfer(
+            ValidAccountId::try_from(alice()).unwrap(),
+            ntoy(20).into(),
+            "deposit".to_string(),
+        );
+        assert_eq!(emulator.contract.get_account_unstaked_balance(alice().try_into().unwrap()).0, ntoy(20));
+        assert_eq!(emulator.contract.get_account_tickets_amount(alice().try_into().unwrap()).0, 0);
+
+        // The native balance growth isn't a reward of a token pool, the yield source funds it.
+        emulator.skip_epochs(1);
+        emulator.amount += ntoy(10);
+        emulator.update_context("usn".to_string(), 0);
+        emulator.contract.ft_on_transfer(
+            ValidAccountId::try_from("strategy".to_string()).unwrap(),
+            ntoy(5).into(),
+            "yield".to_string(),
+        );
+        assert_eq!(emulator.contract.get_prize_pot().0, ntoy(5));
+    }
+
+    #[test]
+    fn test_token_withdraw_failed() {
+        let mut emulator = token_pool_emulator();
+        emulator.update_context("usn".to_string(), 0);
+        emulator.contract.ft_on_transfer(
+            ValidAccountId::try_from(alice()).unwrap(),
+            ntoy(20).into(),
+            "deposit".to_string(),
+        );
+        emulator.update_context(alice(), 0);
+        emulator.contract.withdraw_all();
+        // The account stays registered until the transfer succeeds.
+        assert!(emulator.contract.storage_balance_of(alice().try_into().unwrap()).is_some());
+
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Failed);
+        emulator.contract.on_token_sent(alice(), ntoy(20).into());
+        assert_eq!(emulator.contract.get_account_unstaked_balance(alice().try_into().unwrap()).0, ntoy(20));
+        assert!(emulator.contract.storage_balance_of(alice().try_into().unwrap()).is_some());
+
+        emulator.update_context(alice(), 0);
+        emulator.contract.withdraw_all();
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Successful(vec![]));
+        emulator.contract.on_token_sent(alice(), ntoy(20).into());
+        assert!(emulator.contract.storage_balance_of(alice().try_into().unwrap()).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the yield source can fund the prize pot")]
+    fn test_token_yield_from_stranger() {
+        let mut emulator = token_pool_emulator();
+        emulator.update_context("usn".to_string(), 0);
+        emulator.contract.ft_on_transfer(
+            ValidAccountId::try_from(bob()).unwrap(),
+            ntoy(5).into(),
+            "yield".to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The pool only accepts usn deposits")]
+    fn test_token_pool_rejects_near() {
+        let mut emulator = token_pool_emulator();
+        emulator.update_context(bob(), ntoy(1_00));
+        emulator.contract.deposit();
+    }
+
+    #[test]
+    fn test_external_staking_pool_yield() {
+        let config = PoolConfig {
+            yield_source: YieldSource::ExternalStakingPool {
+                pool_id: "validator".to_string(),
+            },
+            ..Default::default()
+        };
+        let mut emulator = Emulator::new_with_config(
+            owner(),
+            "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_owned(),
+            zero_fee(),
+            Some(config),
+        );
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit(bob(), deposit_amount);
+        emulator.update_context(bob(), 0);
+        emulator.contract.stake(deposit_amount.into());
+        let delegation = emulator.contract.get_delegation();
+        assert_eq!(delegation.staked_balance, deposit_amount);
+        assert_eq!(delegation.pending_actions, 1);
+
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Successful(vec![]));
+        emulator.contract.on_delegation_staked(deposit_amount.into());
+        let nonce = emulator.contract.get_delegation().nonce;
+        assert_eq!(emulator.contract.get_delegation().pending_actions, 0);
+
+        // A query started before the last delegation action is ignored.
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(
+            emulator.context.clone(),
+            PromiseResult::Successful(serde_json::to_vec(&U128(ntoy(1_05))).unwrap()),
+        );
+        assert!(!emulator.contract.on_delegation_balance((nonce - 1).into()));
+        assert_eq!(emulator.contract.get_prize_pot().0, 0);
+
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(
+            emulator.context.clone(),
+            PromiseResult::Successful(serde_json::to_vec(&U128(ntoy(1_05))).unwrap()),
+        );
+        assert!(emulator.contract.on_delegation_balance(nonce.into()));
+        assert_eq!(emulator.contract.get_prize_pot().0, ntoy(5));
+        // The prize pot is unstaked from the external pool to become liquid.
+        let delegation = emulator.contract.get_delegation();
+        assert_eq!(delegation.staked_balance, deposit_amount);
+        assert_eq!(delegation.unstaked_balance, ntoy(5));
+        assert_eq!(emulator.contract.get_account_staked_balance(bob().try_into().unwrap()).0, deposit_amount);
+    }
+
+    #[test]
+    #[should_panic(expected = "The unstaked balance is still being withdrawn, try again later")]
+    fn test_external_staking_pool_withdraw_not_liquid() {
+        let config = PoolConfig {
+            yield_source: YieldSource::ExternalStakingPool {
+                pool_id: "validator".to_string(),
+            },
+            ..Default::default()
+        };
+        let mut emulator = Emulator::new_with_config(
+            owner(),
+            "KuTCtARNzxZQ3YvXDeLjx83FDqxv2SdQTSbiq876zR7".to_owned(),
+            zero_fee(),
+            Some(config),
+        );
+        let deposit_amount = ntoy(1_00);
+        emulator.update_context(bob(), deposit_amount);
+        emulator.contract.deposit();
+        emulator.update_context(bob(), 0);
+        emulator.contract.stake(deposit_amount.into());
+        // The deposit was attached to the external pool call.
+        emulator.update_context(bob(), 0);
+        emulator.contract.unstake_all();
+        emulator.skip_epochs(4);
+        emulator.update_context(bob(), 0);
+        emulator.contract.withdraw_all();
+    }
+
+    #[test]
+    fn test_roles() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.grant_role(Role::Operator, ValidAccountId::try_from(alice()).unwrap());
+        emulator.contract.grant_role(Role::Guardian, ValidAccountId::try_from(bob()).unwrap());
+        assert_eq!(emulator.contract.get_role_members(Role::Operator), vec![alice()]);
+        assert!(emulator.contract.has_role(Role::Guardian, bob().try_into().unwrap()));
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.pause_staking();
+        assert!(emulator.contract.get_pause_status().staking);
+        emulator.update_context(alice(), 0);
+        emulator.contract.resume_staking();
+        assert!(!emulator.contract.get_pause_status().staking);
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.renounce_role(Role::Guardian);
+        assert!(emulator.contract.get_role_members(Role::Guardian).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only be called by the owner or the [Operator] role")]
+    fn test_guardian_cant_resume_staking() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.grant_role(Role::Guardian, ValidAccountId::try_from(bob()).unwrap());
+        emulator.update_context(bob(), 0);
+        emulator.contract.pause_staking();
+        emulator.contract.resume_staking();
+    }
+
+    #[test]
+    fn test_ownership_transfer() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.propose_owner(Some(ValidAccountId::try_from(alice()).unwrap()));
+        assert_eq!(emulator.contract.get_owner_id(), owner());
+        assert_eq!(emulator.contract.get_proposed_owner_id(), Some(alice()));
+
+        emulator.update_context(alice(), 0);
+        emulator.contract.accept_owner();
+        assert_eq!(emulator.contract.get_owner_id(), alice());
+        assert_eq!(emulator.contract.get_proposed_owner_id(), None);
+        emulator.contract.set_prize_tiers(vec![5000, 5000]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only be called by the proposed owner")]
+    fn test_accept_owner_not_proposed() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.propose_owner(Some(ValidAccountId::try_from(alice()).unwrap()));
+        emulator.update_context(bob(), 0);
+        emulator.contract.accept_owner();
+    }
+
+    /// Writes the state of a pool deployed before the versioning in its own layout. Bob staked
+    /// 100 NEAR for 10 tickets, one slot each, alice has 5 NEAR unstaked and the prize pot holds
+    /// the given reward.
+    fn write_unversioned_state(emulator: &mut Emulator, total_reward: Balance) {
+        let staked_amount = ntoy(1_00);
+        let unstaked_amount = ntoy(5);
+        emulator.locked_amount += staked_amount + total_reward;
+        emulator.amount += unstaked_amount;
+        for account_id in [alice(), bob()].iter() {
+            emulator.contract.storage_deposits.remove(account_id);
+        }
+        let write_account = |account_id: AccountId, unstaked_balance: Balance, stake_shares: Balance, tickets_amount: u64| {
+            let key = [b"a".to_vec(), account_id.try_to_vec().unwrap()].concat();
+            let account = (account_id, unstaked_balance, stake_shares, stake_shares, 3u64, tickets_amount, 1u64, false, 0u64);
+            env::storage_write(&key, &account.try_to_vec().unwrap());
+        };
+        write_account(bob(), 0, staked_amount, 10);
+        write_account(alice(), unstaked_amount, 0, 0);
+        for slot in 0..10u64 {
+            let key = [b"t".to_vec(), slot.to_le_bytes().to_vec()].concat();
+            env::storage_write(&key, &bob().try_to_vec().unwrap());
+        }
+        // The legacy pools added the reward to the total staked balance too.
+        let state = (
+            owner(),
+            b"a".to_vec(),
+            10u64,
+            (10u64, b"t".to_vec()),
+            emulator.contract.next_prize_event_epoch_height,
+            total_reward,
+            emulator.contract.total_staked_balance + staked_amount + total_reward,
+            emulator.amount + emulator.locked_amount,
+            emulator.contract.total_stake_shares + staked_amount,
+            emulator.contract.stake_public_key.clone(),
+            0u64,
+            false,
+            nft_contract(),
+            (0u64, b"h".to_vec()),
+        );
+        env::storage_write(b"STATE", &state.try_to_vec().unwrap());
+        env::storage_remove(STATE_VERSION_KEY);
+    }
+
+    #[test]
+    fn test_migrate_unversioned_state() {
+        let mut emulator = emulator();
+        let staked_amount = ntoy(1_00);
+        let unstaked_amount = ntoy(5);
+        write_unversioned_state(&mut emulator, 0);
+        assert_eq!(emulator.contract.get_state_version(), 0);
+
+        emulator.update_context(staking(), 0);
+        emulator.contract = StakingPool::migrate();
+        assert_eq!(emulator.contract.get_state_version(), STATE_VERSION);
+        assert_eq!(emulator.contract.get_owner_id(), owner());
+        assert_eq!(
+            emulator.contract.get_account_staked_balance(bob().try_into().unwrap()).0,
+            staked_amount
+        );
+        assert!(emulator.contract.storage_balance_of(bob().try_into().unwrap()).is_some());
+        assert_eq!(emulator.contract.get_total_tickets().0, 0);
+
+        // The tickets move to the ledger in batches.
+        emulator.update_context(owner(), 0);
+        assert_eq!(emulator.contract.migrate_legacy_tickets(4.into()).0, 6);
+        assert_eq!(emulator.contract.migrate_legacy_tickets(10.into()).0, 0);
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 10);
+        assert_eq!(emulator.contract.get_total_tickets().0, 10);
+
+        // The other accounts are converted as they change.
+        emulator.update_context(alice(), 0);
+        emulator.contract.withdraw(unstaked_amount.into());
+        emulator.amount -= unstaked_amount;
+        assert_eq!(emulator.contract.get_account_unstaked_balance(alice().try_into().unwrap()).0, 0);
+        emulator.update_context(bob(), 0);
+        emulator.contract.unstake(ntoy(50).into());
+        emulator.simulate_stake_call();
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 5);
+        assert_eq!(
+            emulator.contract.get_account_unstaked_balance(bob().try_into().unwrap()).0,
+            ntoy(50)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The legacy tickets are being migrated")]
+    fn test_migrate_unversioned_state_pending_tickets() {
+        let mut emulator = emulator();
+        emulator.contract.legacy_ticket_slots = Some(Vector::new(b"t".to_vec()));
+        emulator.update_context(bob(), ntoy(10));
+        emulator.contract.deposit();
+    }
+
+    #[test]
+    fn test_migrate_unversioned_state_with_reward() {
+        let mut emulator = emulator();
+        let total_staked_balance = emulator.contract.total_staked_balance;
+        let total_reward = ntoy(7);
+        write_unversioned_state(&mut emulator, total_reward);
+
+        emulator.update_context(staking(), 0);
+        emulator.contract = StakingPool::migrate();
+        // The reward stays in the prize pot, it doesn't raise the share price.
+        assert_eq!(emulator.contract.get_prize_pot().0, total_reward);
+        assert_eq!(emulator.contract.total_staked_balance, total_staked_balance + ntoy(1_00));
+        assert_eq!(
+            emulator.contract.get_account_staked_balance(bob().try_into().unwrap()).0,
+            ntoy(1_00)
+        );
+        assert_eq!(
+            emulator.contract.get_delegation().reserved_balance,
+            emulator.contract.total_staked_balance
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown state version")]
+    fn test_migrate_unknown_version() {
+        let mut emulator = emulator();
+        env::state_write(&emulator.contract);
+        env::storage_write(STATE_VERSION_KEY, &(STATE_VERSION + 1).to_le_bytes());
+        emulator.update_context(staking(), 0);
+        StakingPool::migrate();
+    }
+
+    #[test]
+    #[should_panic(expected = "Can only be called by the owner")]
+    fn test_upgrade_not_owner() {
+        let mut emulator = emulator();
+        emulator.update_context(bob(), 0);
+        emulator.contract.upgrade(vec![0u8; 8].into());
+    }
+
+    #[test]
+    fn test_events() {
+        let mut emulator = emulator();
+        let deposit_amount = ntoy(1_00);
+        emulator.update_context(bob(), deposit_amount);
+        emulator.contract.deposit();
+        assert_eq!(
+            near_sdk::test_utils::get_logs(),
+            vec![format!(
+                "EVENT_JSON:{{\"standard\":\"cherry_pool\",\"version\":\"1.0.0\",\"event\":\"deposit\",\"data\":[{{\"account_id\":\"{}\",\"amount\":\"{}\"}}]}}",
+                bob(),
+                deposit_amount
+            )]
+        );
+        emulator.amount += deposit_amount;
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.stake(deposit_amount.into());
+        let logs = near_sdk::test_utils::get_logs();
+        assert!(logs[0].starts_with("EVENT_JSON:{\"standard\":\"cherry_pool\",\"version\":\"1.0.0\",\"event\":\"stake\""));
+        assert!(logs.contains(&format!(
+            "EVENT_JSON:{{\"standard\":\"cherry_pool\",\"version\":\"1.0.0\",\"event\":\"tickets_added\",\"data\":[{{\"account_id\":\"{}\",\"tickets\":\"10\",\"total_tickets\":\"10\"}}]}}",
+            bob()
+        )));
+        emulator.simulate_stake_call();
+
+        let locked_amount = emulator.locked_amount;
+        emulator.skip_epochs(1);
+        let reward = emulator.locked_amount - locked_amount;
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        assert!(near_sdk::test_utils::get_logs().contains(&format!(
+            "EVENT_JSON:{{\"standard\":\"cherry_pool\",\"version\":\"1.0.0\",\"event\":\"reward_distributed\",\"data\":[{{\"epoch_height\":\"1\",\"total_reward\":\"{}\",\"prize_pot\":\"{}\",\"owner_fee_shares\":\"0\",\"treasury_fee_shares\":\"0\"}}]}}",
+            reward, reward
+        )));
+
+        emulator.update_context(bob(), 1);
+        emulator.contract.ft_transfer(ValidAccountId::try_from(alice()).unwrap(), ntoy(1).into(), Some("gift".to_string()));
+        assert_eq!(
+            near_sdk::test_utils::get_logs()[0],
+            format!(
+                "EVENT_JSON:{{\"standard\":\"nep141\",\"version\":\"1.0.0\",\"event\":\"ft_transfer\",\"data\":[{{\"old_owner_id\":\"{}\",\"new_owner_id\":\"{}\",\"amount\":\"{}\",\"memo\":\"gift\"}}]}}",
+                bob(),
+                alice(),
+                ntoy(1)
+            )
+        );
+    }
+
+    #[test]
+    fn test_json_amounts() {
+        let mut emulator = emulator();
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit(bob(), deposit_amount);
+
+        let account = serde_json::to_value(emulator.contract.get_account(bob().try_into().unwrap())).unwrap();
+        assert_eq!(account["unstaked_balance"], deposit_amount.to_string());
+        assert_eq!(account["tickets_amount"], "0");
+
+        let config: PoolConfig = serde_json::from_str(
+            r#"{"draw_period_epochs":"7","ticket_price":"5000000000000000000000000","num_epochs_to_unlock":"4","pool_threshold":null,"deposit_token_id":null,"yield_source":"NativeStaking"}"#,
+        )
+        .unwrap();
+        assert_eq!(config.ticket_price, ntoy(5));
+        let config = serde_json::to_value(config).unwrap();
+        assert_eq!(config["draw_period_epochs"], "7");
+        assert_eq!(config["ticket_price"], ntoy(5).to_string());
+    }
+
+    fn nft_contract() -> AccountId {
+        "cherrypub_nft.testnet".to_string()
+    }
+
+    /// Transfers an NFT of `account_id` with the given metadata to the pool and resolves the
+    /// metadata lookup. Returns whether the pool sends the token back.
+    fn boost_with_nft(
+        emulator: &mut Emulator,
+        account_id: AccountId,
+        token_id: &str,
+        metadata: serde_json::Value,
+    ) -> bool {
+        emulator.update_context(nft_contract(), 0);
+        let result = emulator.contract.nft_on_transfer(
+            account_id.clone(),
+            account_id.clone(),
+            token_id.to_string(),
+            "{\"boost\":true}".to_string(),
+        );
+        assert!(matches!(result, PromiseOrValue::Promise(_)));
+
+        emulator.update_context(staking(), 0);
+        let token = serde_json::json!({
+            "token_id": token_id,
+            "owner_id": staking(),
+            "metadata": metadata,
+        });
+        testing_env_with_promise_results(
+            emulator.context.clone(),
+            PromiseResult::Successful(serde_json::to_vec(&token).unwrap()),
+        );
+        emulator.contract.on_nft_boost_token(account_id, token_id.to_string())
+    }
+
+    #[test]
+    fn test_nft_boost_follows_tickets() {
+        let mut emulator = emulator();
+        let deposit_amount = ntoy(2_00);
+        emulator.deposit(bob(), deposit_amount);
+        emulator.update_context(bob(), 0);
+        emulator.contract.stake(ntoy(1_00).into());
+        emulator.simulate_stake_call();
+        let metadata = serde_json::json!({ "title": "Cherry Cake" });
+        assert!(!boost_with_nft(&mut emulator, bob(), "11", metadata));
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 15);
+        emulator.contract.assert_tickets_invariant();
+
+        // The tickets of a later stake are boosted too.
+        emulator.update_context(bob(), 0);
+        emulator.contract.stake(ntoy(1_00).into());
+        emulator.simulate_stake_call();
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 30);
+        emulator.contract.assert_tickets_invariant();
+
+        // A partial unstake keeps the boost and removes the bonus of the removed tickets only.
+        emulator.update_context(bob(), 0);
+        emulator.contract.unstake(ntoy(70).into());
+        emulator.simulate_stake_call();
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 19);
+        let nft_boost = emulator
+            .contract
+            .get_account_nft_boost(bob().try_into().unwrap())
+            .unwrap();
+        assert_eq!(nft_boost.bonus_tickets, 6);
+        assert_eq!(emulator.contract.get_account(bob().try_into().unwrap()).tickets_multiplier_bps, 15_000);
+        emulator.contract.assert_tickets_invariant();
+
+        // Shares move without the bonus tickets, which follow the tickets left to the sender.
+        let shares = emulator.contract.ft_balance_of(ValidAccountId::try_from(bob()).unwrap()).0;
+        emulator.update_context(bob(), 1);
+        emulator.contract.ft_transfer(ValidAccountId::try_from(alice()).unwrap(), (shares / 2).into(), None);
+        emulator.amount += 1;
+        assert_eq!(emulator.contract.get_account_tickets_amount(alice().try_into().unwrap()).0, 6);
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 10);
+        assert_eq!(emulator.contract.get_total_tickets().0, 16);
+        emulator.contract.assert_tickets_invariant();
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.unstake_all();
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 0);
+        assert_eq!(emulator.contract.get_total_tickets().0, 6);
+        emulator.contract.assert_tickets_invariant();
+    }
+
+    #[test]
+    fn test_nft_boost() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob(), alice()], &[deposit_amount, deposit_amount]);
+
+        // The legend edition is found by its rarity attribute.
+        let metadata = serde_json::json!({
+            "title": "Cherry Wine",
+            "extra": "{\"rarity\":\"legend\"}",
+        });
+        assert!(!boost_with_nft(&mut emulator, bob(), "1", metadata));
+        let account = emulator.contract.get_account(bob().try_into().unwrap());
+        assert_eq!(account.tickets_multiplier_bps, 50_000);
+        assert_eq!(account.tickets_amount.0, 50);
+        let nft_boost = emulator
+            .contract
+            .get_account_nft_boost(bob().try_into().unwrap())
+            .unwrap();
+        assert_eq!(nft_boost.token_id, "1");
+        assert_eq!(nft_boost.rarity, "legend");
+        assert_eq!(nft_boost.bonus_tickets, 40);
+        assert_eq!(emulator.contract.nft_depositors.get(&"1".to_string()), Some(bob()));
+
+        // A token without attributes is found by its title, the multiplier can be fractional.
+        let metadata = serde_json::json!({ "title": "Cherry Cake" });
+        assert!(!boost_with_nft(&mut emulator, alice(), "11", metadata));
+        let account = emulator.contract.get_account(alice().try_into().unwrap());
+        assert_eq!(account.tickets_multiplier_bps, 15_000);
+        assert_eq!(account.tickets_amount.0, 15);
+        assert_eq!(emulator.contract.get_total_tickets().0, 65);
+    }
+
+    #[test]
+    fn test_nft_boost_unknown_rarity() {
+        let mut emulator = emulator();
+        let metadata = serde_json::json!({
+            "title": "Cherry Wine",
+            "extra": "{\"rarity\":\"mythic\"}",
+        });
+        assert!(boost_with_nft(&mut emulator, bob(), "1", metadata));
+        assert!(emulator
+            .contract
+            .get_account_nft_boost(bob().try_into().unwrap())
+            .is_none());
+        assert_eq!(emulator.contract.nft_depositors.get(&"1".to_string()), None);
+    }
+
+    #[test]
+    fn test_set_nft_multipliers() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+
+        let nft_multipliers = vec![NftMultiplier {
+            rarity: "legend".to_string(),
+            title: "Cherry Wine".to_string(),
+            multiplier_bps: 22_500,
+        }];
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_nft_multipliers(nft_multipliers.clone());
+        assert_eq!(emulator.contract.get_nft_multipliers(), nft_multipliers);
+
+        let metadata = serde_json::json!({ "title": "Cherry Wine" });
+        assert!(!boost_with_nft(&mut emulator, bob(), "1", metadata));
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 22);
+        // The rarities left out of the table don't boost anymore.
+        let metadata = serde_json::json!({ "title": "Cherry Cake" });
+        assert!(boost_with_nft(&mut emulator, alice(), "11", metadata));
+    }
+
+    #[test]
+    #[should_panic(expected = "The multiplier should be between 10000 and 100000 basis points")]
+    fn test_nft_multiplier_below_one() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_nft_multipliers(vec![NftMultiplier {
+            rarity: "common".to_string(),
+            title: "Cherry Cake".to_string(),
+            multiplier_bps: 5_000,
+        }]);
+    }
+
+    #[test]
+    fn test_withdraw_nft_boost() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+        let metadata = serde_json::json!({ "title": "Cherry Cocktail" });
+        assert!(!boost_with_nft(&mut emulator, bob(), "2", metadata));
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 30);
+        let staked_nfts = emulator.contract.get_staked_nfts(0.into(), 10.into());
+        assert_eq!(staked_nfts.len(), 1);
+        assert_eq!(staked_nfts[0].token_id, "2");
+        assert_eq!(staked_nfts[0].account_id, bob());
+        assert_eq!(staked_nfts[0].multiplier_bps, 30_000);
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.withdraw_nft_boost();
+        emulator.amount -= 1;
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 10);
+        assert_eq!(emulator.contract.get_total_tickets().0, 10);
+        assert!(emulator
+            .contract
+            .get_account_nft_boost(bob().try_into().unwrap())
+            .is_none());
+        // The token is listed until the transfer back succeeds.
+        assert_eq!(emulator.contract.get_number_of_staked_nfts().0, 1);
+
+        // A failed transfer keeps the token in the pool and boosts the account again.
+        let nft_boost = NftBoost {
+            token_id: "2".to_string(),
+            rarity: "rare".to_string(),
+            multiplier_bps: 30_000,
+            bonus_tickets: 20,
+        };
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Failed);
+        emulator.contract.on_nft_boost_returned(bob(), nft_boost.clone());
+        let kept_nft_boost = emulator
+            .contract
+            .get_account_nft_boost(bob().try_into().unwrap())
+            .unwrap();
+        assert_eq!(kept_nft_boost.token_id, "2");
+        assert_eq!(kept_nft_boost.bonus_tickets, 20);
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 30);
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.withdraw_nft_boost();
+        emulator.amount -= 1;
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 10);
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Successful(vec![]));
+        emulator.contract.on_nft_boost_returned(bob(), nft_boost);
+        assert_eq!(emulator.contract.get_number_of_staked_nfts().0, 0);
+    }
+
+    #[test]
+    fn test_nft_boost_returned_while_boosted() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+        let metadata = serde_json::json!({ "title": "Cherry Cocktail" });
+        assert!(!boost_with_nft(&mut emulator, bob(), "2", metadata));
+        emulator.update_context(bob(), 0);
+        emulator.contract.withdraw_nft_boost();
+        emulator.amount -= 1;
+        let metadata = serde_json::json!({ "title": "Cherry Cake" });
+        assert!(!boost_with_nft(&mut emulator, bob(), "3", metadata));
+
+        // The boost of the other NFT stays, the token of the failed transfer can be withdrawn.
+        let nft_boost = NftBoost {
+            token_id: "2".to_string(),
+            rarity: "rare".to_string(),
+            multiplier_bps: 30_000,
+            bonus_tickets: 20,
+        };
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Failed);
+        emulator.contract.on_nft_boost_returned(bob(), nft_boost);
+        let kept_nft_boost = emulator
+            .contract
+            .get_account_nft_boost(bob().try_into().unwrap())
+            .unwrap();
+        assert_eq!(kept_nft_boost.token_id, "3");
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 15);
+        assert_eq!(
+            emulator.contract.get_account_unreturned_nfts(bob().try_into().unwrap()),
+            vec!["2".to_string()]
+        );
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.withdraw_unreturned_nft("2".to_string());
+        emulator.amount -= 1;
+        assert!(emulator
+            .contract
+            .get_account_unreturned_nfts(bob().try_into().unwrap())
+            .is_empty());
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Successful(vec![]));
+        emulator.contract.on_unreturned_nft_sent(bob(), "2".to_string());
+        let staked_nfts = emulator.contract.get_staked_nfts(0.into(), 10.into());
+        assert_eq!(staked_nfts.len(), 1);
+        assert_eq!(staked_nfts[0].token_id, "3");
+    }
+
+    #[test]
+    fn test_nft_boost_returned_to_unregistered_account() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+        let metadata = serde_json::json!({ "title": "Cherry Cocktail" });
+        assert!(!boost_with_nft(&mut emulator, bob(), "2", metadata));
+        emulator.update_context(bob(), 0);
+        emulator.contract.unstake_all();
+        emulator.amount -= 1;
+        emulator.simulate_stake_call();
+        emulator.skip_epochs(4);
+        emulator.update_context(bob(), 0);
+        emulator.contract.withdraw_all();
+        emulator.update_context(bob(), 1);
+        emulator.contract.storage_unregister(None);
+        assert!(emulator.contract.storage_balance_of(bob().try_into().unwrap()).is_none());
+
+        // The token of the failed transfer waits for the account without boosting it again.
+        let nft_boost = NftBoost {
+            token_id: "2".to_string(),
+            rarity: "rare".to_string(),
+            multiplier_bps: 30_000,
+            bonus_tickets: 20,
+        };
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Failed);
+        emulator.contract.on_nft_boost_returned(bob(), nft_boost);
+        assert!(emulator.contract.nft_boosts.get(&bob()).is_none());
+        assert_eq!(
+            emulator.contract.get_account_unreturned_nfts(bob().try_into().unwrap()),
+            vec!["2".to_string()]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The NFT 2 isn't waiting to be returned")]
+    fn test_withdraw_unreturned_nft_not_unreturned() {
+        let mut emulator = emulator();
+        emulator.update_context(bob(), 0);
+        emulator.contract.withdraw_unreturned_nft("2".to_string());
+    }
+
+    #[test]
+    fn test_unstake_all_returns_nft_boost() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+        let metadata = serde_json::json!({ "title": "Cherry Wine" });
+        assert!(!boost_with_nft(&mut emulator, bob(), "1", metadata));
+
+        emulator.update_context(bob(), 0);
+        let result = emulator.contract.unstake_all();
+        assert_eq!(result.tickets_removed.0, 50);
+        assert_eq!(emulator.contract.get_total_tickets().0, 0);
+        assert!(emulator
+            .contract
+            .get_account_nft_boost(bob().try_into().unwrap())
+            .is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The account has no NFT boost")]
+    fn test_withdraw_nft_boost_without_boost() {
+        let mut emulator = emulator();
+        emulator.update_context(bob(), 0);
+        emulator.contract.withdraw_nft_boost();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only NFTs of cherrypub_nft.testnet can boost tickets")]
+    fn test_nft_boost_from_other_contract() {
+        let mut emulator = emulator();
+        emulator.update_context(alice(), 0);
+        emulator.contract.nft_on_transfer(bob(), bob(), "7".to_string(), "{\"boost\":true}".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown NFT transfer message")]
+    fn test_nft_transfer_without_boost() {
+        let mut emulator = emulator();
+        emulator.update_context(nft_contract(), 0);
+        emulator.contract.nft_on_transfer(bob(), bob(), "7".to_string(), "Transfered".to_string());
+    }
+
+    fn loyalty_rules() -> LoyaltyRules {
+        LoyaltyRules {
+            points_per_epoch: 2,
+            points_per_amount_unit: 1,
+            amount_unit: ntoy(30),
+            points_per_draw: 5,
+        }
+    }
+
+    #[test]
+    fn test_loyalty_points() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_loyalty_rules(loyalty_rules());
+        assert_eq!(emulator.contract.get_loyalty_rules(), loyalty_rules());
+
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit_and_stake(bob(), deposit_amount);
+        assert_eq!(emulator.contract.get_account_loyalty_points(bob().try_into().unwrap()).0, 0);
+
+        // Every epoch earns 2 points and 1 point per 30 NEAR staked.
+        emulator.skip_epochs(3);
+        emulator.update_context(bob(), 0);
+        assert_eq!(emulator.contract.get_account_loyalty_points(bob().try_into().unwrap()).0, 15);
+
+        // The draw adds 5 points.
+        emulator.skip_epochs(11);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        emulator.simulate_stake_call();
+        assert_eq!(emulator.contract.get_number_of_draws().0, 1);
+        assert_eq!(emulator.contract.get_account_loyalty_points(bob().try_into().unwrap()).0, 75);
+        assert_eq!(emulator.contract.get_account(bob().try_into().unwrap()).loyalty_points.0, 75);
+
+        // Points earned so far are kept when the balance changes.
+        emulator.update_context(bob(), 0);
+        emulator.contract.unstake_all();
+        emulator.skip_epochs(2);
+        emulator.update_context(bob(), 0);
+        assert_eq!(emulator.contract.get_account_loyalty_points(bob().try_into().unwrap()).0, 75);
+        assert_eq!(emulator.contract.get_account_loyalty_points(alice().try_into().unwrap()).0, 0);
+    }
+
+    #[test]
+    fn test_loyalty_reward() {
+        let mut emulator = emulator();
+        let loyalty_rewards = vec![LoyaltyReward {
+            rarity: "common".to_string(),
+            points: 10,
+        }];
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_loyalty_rules(loyalty_rules());
+        emulator.contract.set_loyalty_rewards(loyalty_rewards.clone());
+
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit_and_stake(bob(), deposit_amount);
+        emulator.skip_epochs(5);
+        emulator.update_context(bob(), 0);
+        assert_eq!(emulator.contract.get_account_loyalty_points(bob().try_into().unwrap()).0, 25);
+        // There are no reward NFTs yet.
+        assert!(emulator.contract.get_account_eligible_rewards(bob().try_into().unwrap()).is_empty());
+
+        emulator.update_context(nft_contract(), 0);
+        let result = emulator.contract.nft_on_transfer(
+            owner(),
+            owner(),
+            "11".to_string(),
+            "{\"reward\":\"common\"}".to_string(),
+        );
+        assert!(matches!(result, PromiseOrValue::Value(false)));
+        assert_eq!(emulator.contract.get_reward_nfts("common".to_string()), vec!["11".to_string()]);
+        assert_eq!(emulator.contract.get_loyalty_rewards()[0].available, 1);
+        assert_eq!(
+            emulator.contract.get_account_eligible_rewards(bob().try_into().unwrap()),
+            loyalty_rewards
+        );
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.claim_loyalty_reward("common".to_string());
+        emulator.amount -= 1;
+        assert_eq!(emulator.contract.get_account_loyalty_points(bob().try_into().unwrap()).0, 15);
+        assert!(emulator.contract.get_reward_nfts("common".to_string()).is_empty());
+        assert_eq!(emulator.contract.get_loyalty_rewards()[0].available, 0);
+
+        // A failed transfer gives the points and the NFT back.
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Failed);
+        emulator.contract.on_loyalty_reward_sent(bob(), "common".to_string(), "11".to_string(), 10.into());
+        assert_eq!(emulator.contract.get_account_loyalty_points(bob().try_into().unwrap()).0, 25);
+        assert_eq!(emulator.contract.get_reward_nfts("common".to_string()), vec!["11".to_string()]);
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.claim_loyalty_reward("common".to_string());
+        emulator.amount -= 1;
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Successful(vec![]));
+        emulator.contract.on_loyalty_reward_sent(bob(), "common".to_string(), "11".to_string(), 10.into());
+        assert_eq!(emulator.contract.get_account_loyalty_points(bob().try_into().unwrap()).0, 15);
+        assert!(near_sdk::test_utils::get_logs()[0].contains("\"event\":\"nft_reward_claimed\""));
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough loyalty points for the common reward")]
+    fn test_loyalty_reward_without_points() {
+        let mut emulator = emulator();
+        emulator.update_context(nft_contract(), 0);
+        emulator.contract.nft_on_transfer(owner(), owner(), "11".to_string(), "{\"reward\":\"common\"}".to_string());
+        emulator.update_context(bob(), 0);
+        emulator.contract.claim_loyalty_reward("common".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "There are no common reward NFTs left")]
+    fn test_loyalty_reward_out_of_stock() {
+        let mut emulator = emulator();
+        emulator.update_context(bob(), 0);
+        emulator.contract.claim_loyalty_reward("common".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can stock reward NFTs")]
+    fn test_stock_reward_nft_not_owner() {
+        let mut emulator = emulator();
+        emulator.update_context(nft_contract(), 0);
+        emulator.contract.nft_on_transfer(bob(), bob(), "11".to_string(), "{\"reward\":\"common\"}".to_string());
+    }
+
+    #[test]
+    fn test_achievement_nft() {
+        let mut emulator = emulator();
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit(bob(), deposit_amount);
+        let achievements = emulator.contract.get_account_achievements(bob().try_into().unwrap());
+        assert_eq!(achievements[0].achievement, Achievement::FirstDeposit);
+        assert!(achievements[0].earned && !achievements[0].claimed);
+        assert!(!achievements[1].earned && !achievements[2].earned);
+
+        // The deposit over the storage of the NFT is refunded, none of it counts as a reward.
+        let last_total_balance = emulator.contract.last_total_balance;
+        emulator.update_context(bob(), ntoy(1));
+        emulator.contract.claim_achievement_nft(Achievement::FirstDeposit);
+        assert!(emulator.contract.get_account_achievements(bob().try_into().unwrap())[0].claimed);
+        assert_eq!(emulator.contract.last_total_balance, last_total_balance);
+
+        // A failed mint lets the account claim the achievement again.
+        let storage_deposit = ACHIEVEMENT_NFT_STORAGE_DEPOSIT;
+        emulator.update_context(staking(), 0);
+        testing_env_with_promise_results(emulator.context.clone(), PromiseResult::Failed);
+        emulator.contract.on_achievement_nft_minted(bob(), Achievement::FirstDeposit, storage_deposit.into());
+        assert!(!emulator.contract.get_account_achievements(bob().try_into().unwrap())[0].claimed);
+        assert_eq!(emulator.contract.last_total_balance, last_total_balance);
+
+        emulator.update_context(bob(), storage_deposit);
+        emulator.contract.claim_achievement_nft(Achievement::FirstDeposit);
+        emulator.update_context(staking(), 0);
+        let token = serde_json::json!({
+            "token_id": "first_deposit:1",
+            "owner_id": bob(),
+        });
+        testing_env_with_promise_results(
+            emulator.context.clone(),
+            PromiseResult::Successful(serde_json::to_vec(&token).unwrap()),
+        );
+        emulator.contract.on_achievement_nft_minted(bob(), Achievement::FirstDeposit, storage_deposit.into());
+        assert!(near_sdk::test_utils::get_logs()[0].contains("\"token_id\":\"first_deposit:1\""));
+        assert!(emulator.contract.get_account_achievements(bob().try_into().unwrap())[0].claimed);
+    }
+
+    #[test]
+    fn test_achievement_nft_small_deposit() {
+        let mut emulator = emulator();
+        // Less than the ticket price doesn't earn the first deposit achievement.
+        emulator.deposit(bob(), ntoy(1));
+        assert!(!emulator.contract.get_account_achievements(bob().try_into().unwrap())[0].earned);
+
+        emulator.deposit(bob(), ntoy(10));
+        assert!(emulator.contract.get_account_achievements(bob().try_into().unwrap())[0].earned);
+    }
+
+    #[test]
+    #[should_panic(expected = "The first_deposit achievement NFT is already claimed")]
+    fn test_achievement_nft_claimed_twice() {
+        let mut emulator = emulator();
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit(bob(), deposit_amount);
+        emulator.update_context(bob(), ACHIEVEMENT_NFT_STORAGE_DEPOSIT);
+        emulator.contract.claim_achievement_nft(Achievement::FirstDeposit);
+        emulator.contract.claim_achievement_nft(Achievement::FirstDeposit);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of at least")]
+    fn test_achievement_nft_no_storage_deposit() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+        emulator.update_context(bob(), ACHIEVEMENT_NFT_STORAGE_DEPOSIT - 1);
+        emulator.contract.claim_achievement_nft(Achievement::FirstDeposit);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account hasn't earned the first_win achievement")]
+    fn test_achievement_nft_not_earned() {
+        let mut emulator = emulator();
+        emulator.update_context(bob(), 0);
+        emulator.contract.claim_achievement_nft(Achievement::FirstWin);
+    }
+
+    #[test]
+    fn test_pause_draws() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+
+        emulator.update_context(owner(), 0);
+        emulator.contract.pause_draws();
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        emulator.simulate_stake_call();
+        assert_eq!(emulator.contract.get_number_of_draws().0, 0);
+
+        emulator.update_context(owner(), 0);
+        emulator.contract.resume_draws();
+        emulator.skip_epochs(1);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        assert_eq!(emulator.contract.get_number_of_draws().0, 1);
+    }
+
+    #[test]
+    fn test_pause_draws_keeps_pending_draw() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_draw_reveal_delay(Some(5.into()));
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit_and_stake(bob(), deposit_amount);
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        emulator.simulate_stake_call();
+        let pending_draw = emulator.contract.get_pending_draw().unwrap();
+
+        // Pausing doesn't cancel the committed draw, it's revealed as committed.
+        emulator.update_context(owner(), 0);
+        emulator.contract.pause_draws();
+        assert_eq!(emulator.contract.get_pending_draw().unwrap().commitment, pending_draw.commitment);
+        emulator.block_index += 5;
+        emulator.update_context(alice(), 0);
+        let payouts = emulator.contract.reveal_draw();
+        assert_eq!(payouts[0].account_id, bob());
+
+        // No new draw is committed until draws are resumed.
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        assert!(emulator.contract.get_pending_draw().is_none());
+        emulator.update_context(owner(), 0);
+        emulator.contract.resume_draws();
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        assert_eq!(emulator.contract.get_pending_draw().unwrap().draw_id, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposits are paused")]
+    fn test_pause_deposits() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.pause_deposits();
+        emulator.update_context(bob(), ntoy(1_00));
+        emulator.contract.deposit();
+    }
+
+    #[test]
+    fn test_emergency_mode() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_draw_reveal_delay(Some(5.into()));
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit_and_stake(bob(), deposit_amount);
+
+        // The committed draw is cancelled, so the tickets can be removed.
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        emulator.simulate_stake_call();
+        assert!(emulator.contract.get_pending_draw().is_some());
+        emulator.update_context(owner(), 0);
+        emulator.contract.enter_emergency_mode();
+        assert!(emulator.contract.get_pending_draw().is_none());
+        assert!(emulator.contract.get_pause_status().emergency);
+
+        emulator.update_context(bob(), 0);
+        let result = emulator.contract.unstake_all();
+        assert_eq!(result.tickets_removed.0, 10);
+        assert_eq!(emulator.contract.get_total_tickets().0, 0);
+
+        emulator.update_context(owner(), 0);
+        emulator.contract.exit_emergency_mode();
+        emulator.update_context(bob(), 0);
+        emulator.contract.stake(ntoy(50).into());
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "The pool is in the emergency mode, only unstaking and withdrawing are allowed")]
+    fn test_emergency_mode_blocks_staking() {
+        let mut emulator = emulator();
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit(bob(), deposit_amount);
+
+        emulator.update_context(owner(), 0);
+        emulator.contract.enter_emergency_mode();
+        emulator.update_context(bob(), 0);
+        emulator.contract.stake(deposit_amount.into());
+    }
+
+    #[test]
+    fn test_add_remove_tickets() {
+        let deposit_amount = ntoy(1_000);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+        assert_eq!(
+            emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0,
+            1_00
+        );
+
+        emulator.skip_epochs(4);
+        emulator.update_context(bob(), 0);
+
+        emulator.contract.unstake((deposit_amount / 2).into());
+        emulator.simulate_stake_call();
+        assert_eq!(
+            emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0,
+            50
+        );
+        assert_eq!(emulator.contract.get_total_tickets().0, 50);
+        assert_eq!(emulator.contract.tickets.tickets_of(&bob()), 50);
+    }
+
+    #[test]
+    fn test_time_weighted_odds() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+
+        // A whale stakes ten times more one epoch before the draw.
+        emulator.skip_epochs(13);
+        let whale_amount = ntoy(1_000);
+        emulator.deposit_and_stake(alice(), whale_amount);
+
+        let bob_odds = emulator.contract.get_account_win_probability(bob().try_into().unwrap());
+        let alice_odds = emulator.contract.get_account_win_probability(alice().try_into().unwrap());
+        assert_eq!(bob_odds.average_tickets.0, 10);
+        assert_eq!(bob_odds.time_weighted_tickets.0, 140);
+        assert_eq!(alice_odds.average_tickets.0, 7);
+        assert_eq!(alice_odds.time_weighted_tickets.0, 100);
+        assert_eq!(alice_odds.total_time_weighted_tickets.0, 240);
+        assert_eq!(alice_odds.grand_prize_ppm.0, 416_666);
+
+        // Unstaking right after the draw keeps nothing for the next period.
+        emulator.skip_epochs(1);
+        emulator.update_context(alice(), 0);
+        emulator.contract.unstake(whale_amount.into());
+        emulator.simulate_stake_call();
+        let alice_odds = emulator.contract.get_account_win_probability(alice().try_into().unwrap());
+        assert_eq!(alice_odds.time_weighted_tickets.0, 0);
+        assert_eq!(alice_odds.total_time_weighted_tickets.0, 140);
+    }
+
+    #[test]
+    fn test_winner_announcement() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+        assert_eq!(
+            emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0,
+            10
+        );
+
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+
+        let (draw_id, winners) = emulator.contract.get_prize_winners();
+        assert_eq!(winners.len(), 1);
+        assert_eq!(winners[0].1, bob());
+        let draw_seed = emulator.contract.get_draw_seed(draw_id.into()).unwrap();
+        // 10 tickets held for the whole 14 epochs draw period.
+        assert_eq!(draw_seed.tickets_count, 140);
+        assert_eq!(
+            emulator.contract.compute_winning_tickets(draw_id.into()),
+            Some(draw_seed.winning_tickets.into_iter().map(U64).collect())
+        );
+
+        let contract_balance = emulator.amount;
+        let reward = emulator.contract.total_reward;
+        let payouts = emulator.contract.award_prizes_to_winners(draw_id, winners);
+        assert_eq!(payouts[0].amount, reward);
+        assert_eq!(emulator.contract.get_draw(draw_id.into()).unwrap().payouts.len(), 1);
+
+        emulator.update_context(bob(), 0);
+        assert_eq!(emulator.amount, contract_balance - reward);
+    }
+
+    #[test]
+    fn test_commit_reveal_draw() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_draw_reveal_delay(Some(5.into()));
+
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit_and_stake(bob(), deposit_amount);
+
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        // Nothing is drawn inline, the ticket snapshot is committed instead.
+        assert!(emulator.contract.get_draw_seed(0.into()).is_none());
+        let pending_draw = emulator.contract.get_pending_draw().unwrap();
+        assert_eq!(pending_draw.draw_id, 0);
+        assert_eq!(pending_draw.tickets_count, 140);
+        assert_eq!(pending_draw.reveal_block_height, emulator.block_index + 5);
+
+        emulator.block_index += 5;
+        emulator.update_context(alice(), 0);
+        let payouts = emulator.contract.reveal_draw();
+        assert_eq!(payouts[0].account_id, bob());
+        assert!(emulator.contract.get_pending_draw().is_none());
+        let draw_seed = emulator.contract.get_draw_seed(0.into()).unwrap();
+        assert_eq!(
+            emulator.contract.compute_winning_tickets(0.into()),
+            Some(draw_seed.winning_tickets.into_iter().map(U64).collect())
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The draw can't be revealed before block")]
+    fn test_reveal_draw_too_early() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_draw_reveal_delay(Some(5.into()));
+
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit_and_stake(bob(), deposit_amount);
+
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        emulator.block_index += 4;
+        emulator.update_context(bob(), 0);
+        emulator.contract.reveal_draw();
+    }
+
+    #[test]
+    #[should_panic(expected = "Tickets are locked until the pending draw is revealed")]
+    fn test_tickets_locked_while_draw_pending() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_draw_reveal_delay(Some(5.into()));
+
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit(bob(), deposit_amount);
+        emulator.update_context(bob(), 0);
+        emulator.contract.stake((deposit_amount / 2).into());
+        emulator.simulate_stake_call();
+
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        emulator.update_context(bob(), 0);
+        emulator.contract.stake((deposit_amount / 2).into());
+    }
+
+    #[test]
+    fn test_tiered_prizes() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_prize_tiers(vec![5000, 1000, 1000, 1000, 1000, 1000]);
+
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit_and_stake(alice(), deposit_amount);
+        emulator.deposit_and_stake(bob(), deposit_amount);
+
+        let locked_amount = emulator.locked_amount;
+        emulator.skip_epochs(14);
+        let reward = emulator.locked_amount - locked_amount;
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+
+        let payouts = emulator.contract.get_draw(0.into()).unwrap().payouts;
+        assert_eq!(payouts.len(), 6);
+        let mut tickets: Vec<u64> = payouts.iter().map(|payout| payout.ticket).collect();
+        tickets.sort_unstable();
+        tickets.dedup();
+        assert_eq!(tickets.len(), 6);
+        assert_eq!(payouts[0].amount, reward / 2);
+        assert_eq!(payouts[1].amount, reward / 10);
+        let paid: Balance = payouts.iter().map(|payout| payout.amount).sum();
+        assert_eq!(paid + emulator.contract.total_reward, reward);
+    }
+
+    #[test]
+    fn test_tiered_prizes_one_winner() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_prize_tiers(vec![5000, 5000]);
+
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit_and_stake(bob(), deposit_amount);
+
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        // Both tiers of the draw make one pending prize.
+        let payouts = emulator.contract.get_draw(0.into()).unwrap().payouts;
+        assert_eq!(payouts.len(), 2);
+        let pending_prizes = emulator.contract.get_account_pending_prizes(bob().try_into().unwrap());
+        assert_eq!(pending_prizes.len(), 1);
+        assert_eq!(pending_prizes[0].amount, payouts[0].amount + payouts[1].amount);
+    }
+
+    #[test]
+    fn test_claim_prize() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+
+        let locked_amount = emulator.locked_amount;
+        emulator.skip_epochs(14);
+        let reward = emulator.locked_amount - locked_amount;
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        emulator.simulate_stake_call();
+        assert_eq!(emulator.contract.get_prize_pot().0, 0);
+        assert_eq!(emulator.contract.get_account_pending_prizes(bob().try_into().unwrap())[0].amount, reward);
+
+        // Compounding the prize stakes it and adds tickets.
+        emulator.update_context(bob(), 0);
+        assert_eq!(emulator.contract.claim_prize(true).0, reward);
+        emulator.simulate_stake_call();
+        assert!(emulator.contract.get_account_pending_prizes(bob().try_into().unwrap()).is_empty());
+        assert_eq_in_near!(
+            emulator.contract.get_account_staked_balance(bob().try_into().unwrap()).0,
+            deposit_amount + reward
+        );
+        assert_eq!(
+            emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0,
+            ((deposit_amount + reward) / emulator.contract.get_config().ticket_price) as u64
+        );
+    }
+
+    #[test]
+    fn test_unclaimed_prizes_expire() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+
+        let mut first_prize = 0;
+        for draw_id in 0..NUM_DRAWS_TO_CLAIM_PRIZE {
+            emulator.skip_epochs(14);
+            emulator.update_context(bob(), 0);
             emulator.contract.ping();
+            emulator.simulate_stake_call();
+            if draw_id == 0 {
+                first_prize = emulator.contract.get_draw(0.into()).unwrap().payouts[0].amount;
+            }
+        }
+        assert_eq!(
+            emulator.contract.get_account_pending_prizes(bob().try_into().unwrap()).len() as u64,
+            NUM_DRAWS_TO_CLAIM_PRIZE
+        );
+
+        // The next draw expires the first prize and puts it back into the pot.
+        let locked_amount = emulator.locked_amount;
+        emulator.skip_epochs(14);
+        let reward = emulator.locked_amount - locked_amount;
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        let payouts = emulator.contract.get_draw(NUM_DRAWS_TO_CLAIM_PRIZE.into()).unwrap().payouts;
+        assert_eq!(payouts[0].amount, reward + first_prize);
+        let pending_prizes = emulator.contract.get_account_pending_prizes(bob().try_into().unwrap());
+        assert_eq!(pending_prizes.len() as u64, NUM_DRAWS_TO_CLAIM_PRIZE);
+        assert!(pending_prizes.iter().all(|prize| prize.draw_id > 0));
+    }
+
+    #[test]
+    fn test_expired_prizes_dont_keep_account() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        emulator.simulate_stake_call();
+        assert_eq!(emulator.contract.get_account_pending_prizes(bob().try_into().unwrap()).len(), 1);
+
+        // Alice wins the next draws while bob leaves without claiming the prize.
+        emulator.deposit_and_stake(alice(), deposit_amount);
+        emulator.update_context(bob(), 0);
+        emulator.contract.unstake_all();
+        emulator.simulate_stake_call();
+        for _ in 0..NUM_DRAWS_TO_CLAIM_PRIZE {
+            emulator.skip_epochs(14);
             emulator.update_context(alice(), 0);
-            amount = 2 + (amount - 1) % 3;
-            emulator.contract.stake(ntoy(amount).into());
+            emulator.contract.ping();
+            emulator.simulate_stake_call();
+        }
+        assert!(emulator.contract.get_account_pending_prizes(bob().try_into().unwrap()).is_empty());
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.withdraw_all();
+        assert!(emulator.contract.storage_balance_of(bob().try_into().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_draw_history() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+
+        for _ in 0..3 {
+            emulator.skip_epochs(14);
+            emulator.update_context(bob(), 0);
+            emulator.contract.ping();
             emulator.simulate_stake_call();
-            remaining -= amount;
         }
+
+        assert_eq!(emulator.contract.get_number_of_draws().0, 3);
+        let draw = emulator.contract.get_draw(1.into()).unwrap();
+        assert_eq!(draw.seed.draw_id, 1);
+        assert_eq!(draw.seed.epoch_height, 28);
+        assert_eq!(draw.seed.tickets_count, 140);
+        assert_eq!(draw.payouts[0].account_id, bob());
+        assert_eq!(draw.payouts[0].amount, draw.prize_pot);
+        assert!(emulator.contract.get_draw(3.into()).is_none());
+
+        let draws = emulator.contract.get_draws(1.into(), 10.into());
+        assert_eq!(draws.len(), 2);
+        assert_eq!(draws[1].seed.draw_id, 2);
+
+        let wins = emulator.contract.get_account_wins(bob().try_into().unwrap());
+        assert_eq!(wins.len(), 3);
+        assert_eq!(wins[2].draw_id, 2);
+        assert!(emulator.contract.get_account_wins(alice().try_into().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_update_config() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.update_config(PoolConfig {
+            draw_period_epochs: 7,
+            ticket_price: ntoy(1),
+            num_epochs_to_unlock: 6,
+            pool_threshold: Some(ntoy(200)),
+            deposit_token_id: None,
+            yield_source: YieldSource::NativeStaking,
+        });
+
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit_and_stake(bob(), deposit_amount);
+        assert_eq!(emulator.contract.get_account_tickets_amount(bob().try_into().unwrap()).0, 100);
+
+        // The first draw keeps its schedule, the next one follows the new period.
+        emulator.skip_epochs(14);
+        emulator.update_context(bob(), 0);
+        emulator.contract.ping();
+        emulator.simulate_stake_call();
+        assert_eq!(emulator.contract.get_number_of_draws().0, 1);
+        assert_eq!(emulator.contract.next_prize_event_epoch_height, 21);
+
+        emulator.update_context(bob(), 0);
+        emulator.contract.unstake(ntoy(10).into());
+        assert_eq!(
+            emulator.contract.get_account(bob().try_into().unwrap()).unstaked_available_epoch_height.0,
+            20
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The pool is full")]
+    fn test_pool_threshold() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        let mut config = emulator.contract.get_config();
+        config.pool_threshold = Some(ntoy(100));
+        emulator.contract.update_config(config);
+
+        let deposit_amount = ntoy(1_00);
+        emulator.deposit(bob(), deposit_amount);
+        emulator.update_context(bob(), 0);
+        emulator.contract.stake(deposit_amount.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "The ticket price can't change while there are tickets")]
+    fn test_ticket_price_locked_by_tickets() {
+        let deposit_amount = ntoy(1_00);
+        let mut emulator = staked_emulator(&[bob()], &[deposit_amount]);
+
+        emulator.update_context(owner(), 0);
+        let mut config = emulator.contract.get_config();
+        config.ticket_price = ntoy(1);
+        emulator.contract.update_config(config);
+    }
+
+    #[test]
+    #[should_panic(expected = "Prize tiers can't share more than the whole prize pot")]
+    fn test_prize_tiers_over_full_pot() {
+        let mut emulator = emulator();
+        emulator.update_context(owner(), 0);
+        emulator.contract.set_prize_tiers(vec![6000, 5000]);
+    }
+
+    #[test]
+    fn test_draw_seed_is_reproducible() {
+        let mut emulator = emulator();
+        emulator.update_context(bob(), 0);
+
+        let random_seed = vec![7; 32];
+        let draw_seed = DrawSeed::new(random_seed.clone(), 3, 42, 1_000, 5);
+        assert_eq!(draw_seed.compute_winning_tickets(), draw_seed.winning_tickets);
+        assert_eq!(
+            DrawSeed::new(random_seed.clone(), 3, 42, 1_000, 5).winning_tickets,
+            draw_seed.winning_tickets
+        );
+        assert_ne!(
+            DrawSeed::new(random_seed, 4, 42, 1_000, 5).winning_tickets,
+            draw_seed.winning_tickets
+        );
+
+        // Drawing every ticket yields each of them exactly once.
+        let mut all_tickets = DrawSeed::new(vec![1; 32], 0, 0, 10, 10).winning_tickets;
+        all_tickets.sort_unstable();
+        assert_eq!(all_tickets, (0..10).collect::<Vec<u64>>());
+    }
+
+    #[test]
+    #[should_panic(expected = "The draw seed doesn't match its commitment")]
+    fn test_draw_seed_tampered_inputs() {
+        let mut emulator = emulator();
+        emulator.update_context(bob(), 0);
+
+        let mut draw_seed = DrawSeed::new(vec![7; 32], 3, 42, 1_000, 5);
+        draw_seed.epoch_height += 1;
+        draw_seed.compute_winning_tickets();
     }
 }
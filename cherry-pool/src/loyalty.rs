@@ -0,0 +1,346 @@
+use crate::*;
+use crate::internal::ext_nft;
+use near_sdk::Gas;
+
+const GAS_FOR_NFT_TRANSFER: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_LOYALTY_REWARD_SENT: Gas = 10_000_000_000_000;
+const ONE_YOCTO: Balance = 1;
+const NO_DEPOSIT: Balance = 0;
+
+/// How accounts earn loyalty points.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LoyaltyRules {
+    /// Points for every epoch the account has a staked balance.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub points_per_epoch: u64,
+    /// Points for every epoch per every `amount_unit` of the staked balance.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub points_per_amount_unit: u64,
+    #[serde(with = "crate::serialize::u128_dec_format")]
+    pub amount_unit: Balance,
+    /// Points for every draw the account has tickets in.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub points_per_draw: u64,
+}
+
+impl Default for LoyaltyRules {
+    fn default() -> Self {
+        Self {
+            points_per_epoch: 1,
+            points_per_amount_unit: 1,
+            amount_unit: 100 * 10u128.pow(24),
+            points_per_draw: 5,
+        }
+    }
+}
+
+impl LoyaltyRules {
+    pub fn assert_valid(&self) {
+        assert!(self.amount_unit > 0, "The amount unit should be positive");
+    }
+}
+
+/// An NFT of the reward catalog, it costs `points` loyalty points.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LoyaltyReward {
+    /// The rarity of the reward NFTs, like the rarities of the NFT multipliers.
+    pub rarity: String,
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub points: u64,
+}
+
+/// A reward of the catalog and the number of its NFTs left.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LoyaltyRewardStatus {
+    pub reward: LoyaltyReward,
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub available: u64,
+}
+
+/// The loyalty points of an account, earned up to `epoch_height` and the draw `draw_id`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LoyaltyAccount {
+    pub points: u64,
+    pub epoch_height: EpochHeight,
+    /// The id of the first draw the points aren't earned for yet.
+    pub draw_id: DrawId,
+}
+
+pub(crate) fn default_loyalty_rewards() -> Vec<LoyaltyReward> {
+    vec![
+        LoyaltyReward {
+            rarity: "common".to_string(),
+            points: 100,
+        },
+        LoyaltyReward {
+            rarity: "rare".to_string(),
+            points: 500,
+        },
+        LoyaltyReward {
+            rarity: "legend".to_string(),
+            points: 2_000,
+        },
+    ]
+}
+
+pub(crate) fn assert_valid_loyalty_rewards(loyalty_rewards: &[LoyaltyReward]) {
+    for (index, loyalty_reward) in loyalty_rewards.iter().enumerate() {
+        assert!(!loyalty_reward.rarity.is_empty(), "The rarity can't be empty");
+        assert!(loyalty_reward.points > 0, "The reward should cost some points");
+        assert!(
+            loyalty_rewards[..index]
+                .iter()
+                .all(|other| other.rarity != loyalty_reward.rarity),
+            "The rarity {} is listed twice",
+            loyalty_reward.rarity
+        );
+    }
+}
+
+/// Accounts earn loyalty points for the time they stake and the draws they take part in, and
+/// spend them on the NFTs of the reward catalog. The owner stocks the reward NFTs by transferring
+/// them to the pool with `nft_transfer_call` and the `{"reward":"<rarity>"}` message.
+#[near_bindgen]
+impl StakingPool {
+    /// Spends the loyalty points of the reward of the given rarity on one of its NFTs. The points
+    /// are given back if the NFT transfer fails.
+    pub fn claim_loyalty_reward(&mut self, rarity: String) -> Promise {
+        self.assert_not_emergency();
+        let account_id = env::predecessor_account_id();
+        self.assert_registered(&account_id);
+        let loyalty_reward = self
+            .loyalty_rewards
+            .iter()
+            .find(|loyalty_reward| loyalty_reward.rarity == rarity)
+            .cloned()
+            .unwrap_or_else(|| env::panic(format!("Unknown reward rarity {}", rarity).as_bytes()));
+        let mut token_ids = self.reward_nfts.get(&rarity).unwrap_or_default();
+        let token_id = token_ids
+            .pop()
+            .unwrap_or_else(|| env::panic(format!("There are no {} reward NFTs left", rarity).as_bytes()));
+
+        self.internal_update_loyalty_points(&account_id);
+        let mut loyalty_account = self.loyalty_accounts.get(&account_id).unwrap();
+        assert!(
+            loyalty_account.points >= loyalty_reward.points,
+            "Not enough loyalty points for the {} reward",
+            rarity
+        );
+        self.reward_nfts.insert(&rarity, &token_ids);
+        loyalty_account.points -= loyalty_reward.points;
+        self.loyalty_accounts.insert(&account_id, &loyalty_account);
+
+        self.last_total_balance -= ONE_YOCTO;
+        ext_nft::nft_transfer(
+            account_id.clone(),
+            token_id.clone(),
+            None,
+            None,
+            &self.nft_contract_id,
+            ONE_YOCTO,
+            GAS_FOR_NFT_TRANSFER,
+        )
+        .then(ext_self::on_loyalty_reward_sent(
+            account_id,
+            rarity,
+            token_id,
+            loyalty_reward.points.into(),
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_ON_LOYALTY_REWARD_SENT,
+        ))
+    }
+
+    /// Callback of sending a reward NFT. A failed transfer gives the points back to the account
+    /// and the NFT back to the catalog.
+    pub fn on_loyalty_reward_sent(
+        &mut self,
+        account_id: AccountId,
+        rarity: String,
+        token_id: TokenId,
+        points: U64,
+    ) {
+        assert_eq!(
+            env::current_account_id(),
+            env::predecessor_account_id(),
+            "Can be called only as a callback"
+        );
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            PoolEvent::NftRewardClaimed(vec![NftData {
+                account_id,
+                token_id,
+            }])
+            .emit();
+            return;
+        }
+        let mut token_ids = self.reward_nfts.get(&rarity).unwrap_or_default();
+        token_ids.push(token_id.clone());
+        self.reward_nfts.insert(&rarity, &token_ids);
+        if let Some(mut loyalty_account) = self.loyalty_accounts.get(&account_id) {
+            loyalty_account.points += points.0;
+            self.loyalty_accounts.insert(&account_id, &loyalty_account);
+        }
+        log!(
+            "Sending NFT {} to @{} failed, {} loyalty points are given back",
+            token_id,
+            account_id,
+            points.0
+        );
+    }
+
+    /// Owner's method.
+    /// Sets how accounts earn loyalty points. The rules apply to the points not counted yet.
+    pub fn set_loyalty_rules(&mut self, loyalty_rules: LoyaltyRules) {
+        self.assert_owner();
+        loyalty_rules.assert_valid();
+        self.loyalty_rules = loyalty_rules;
+    }
+
+    /// Owner's method.
+    /// Sets the reward catalog. The stocked NFTs of the rarities left out stay in the pool until
+    /// their rarity is listed again.
+    pub fn set_loyalty_rewards(&mut self, loyalty_rewards: Vec<LoyaltyReward>) {
+        self.assert_owner();
+        assert_valid_loyalty_rewards(&loyalty_rewards);
+        self.loyalty_rewards = loyalty_rewards;
+    }
+
+    /// Owner's method.
+    /// Moves the given NFTs the pool held before the reward catalog to the rewards of the rarity.
+    pub fn stock_held_reward_nfts(&mut self, rarity: String, token_ids: Vec<TokenId>) {
+        self.assert_owner();
+        self.assert_reward_rarity(&rarity);
+        let mut reward_nfts = self.reward_nfts.get(&rarity).unwrap_or_default();
+        for token_id in token_ids {
+            let index = self
+                .nft_hold
+                .iter()
+                .position(|held_token_id| held_token_id == token_id)
+                .unwrap_or_else(|| env::panic(format!("NFT {} isn't held by the pool", token_id).as_bytes()));
+            self.nft_hold.swap_remove(index as u64);
+            reward_nfts.push(token_id);
+        }
+        self.reward_nfts.insert(&rarity, &reward_nfts);
+    }
+
+    pub fn get_loyalty_rules(&self) -> LoyaltyRules {
+        self.loyalty_rules.clone()
+    }
+
+    /// Returns the reward catalog with the number of NFTs left for every reward.
+    pub fn get_loyalty_rewards(&self) -> Vec<LoyaltyRewardStatus> {
+        self.loyalty_rewards
+            .iter()
+            .map(|loyalty_reward| LoyaltyRewardStatus {
+                reward: loyalty_reward.clone(),
+                available: self
+                    .reward_nfts
+                    .get(&loyalty_reward.rarity)
+                    .map(|token_ids| token_ids.len() as u64)
+                    .unwrap_or(0),
+            })
+            .collect()
+    }
+
+    /// Returns the NFTs left for the reward of the given rarity.
+    pub fn get_reward_nfts(&self, rarity: String) -> Vec<TokenId> {
+        self.reward_nfts.get(&rarity).unwrap_or_default()
+    }
+
+    /// Returns the loyalty points of the given account, including the ones earned since its last
+    /// balance or tickets change.
+    pub fn get_account_loyalty_points(&self, account_id: ValidAccountId) -> U64 {
+        self.internal_loyalty_points(account_id.as_ref()).into()
+    }
+
+    /// Returns the rewards the given account has enough points for and that have NFTs left.
+    pub fn get_account_eligible_rewards(&self, account_id: ValidAccountId) -> Vec<LoyaltyReward> {
+        let points = self.internal_loyalty_points(account_id.as_ref());
+        self.loyalty_rewards
+            .iter()
+            .filter(|loyalty_reward| {
+                loyalty_reward.points <= points
+                    && !self
+                        .reward_nfts
+                        .get(&loyalty_reward.rarity)
+                        .unwrap_or_default()
+                        .is_empty()
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+impl StakingPool {
+    /// Adds the reward NFT the owner transferred to the pool to the rewards of the rarity.
+    pub(crate) fn internal_stock_reward_nft(&mut self, rarity: String, token_id: TokenId) {
+        self.assert_reward_rarity(&rarity);
+        let mut token_ids = self.reward_nfts.get(&rarity).unwrap_or_default();
+        token_ids.push(token_id);
+        self.reward_nfts.insert(&rarity, &token_ids);
+    }
+
+    fn assert_reward_rarity(&self, rarity: &str) {
+        assert!(
+            self.loyalty_rewards
+                .iter()
+                .any(|loyalty_reward| loyalty_reward.rarity == rarity),
+            "Unknown reward rarity {}",
+            rarity
+        );
+    }
+
+    /// Counts the points the account earned since its last update with its current balance and
+    /// tickets. Has to be called before they change.
+    pub(crate) fn internal_update_loyalty_points(&mut self, account_id: &AccountId) {
+        let draws = self.internal_new_draws(account_id);
+        if draws > 0 {
+            self.internal_record_draws(account_id, draws);
+        }
+        let loyalty_account = LoyaltyAccount {
+            points: self.internal_loyalty_points(account_id),
+            epoch_height: env::epoch_height(),
+            draw_id: self.next_draw_id,
+        };
+        self.loyalty_accounts.insert(account_id, &loyalty_account);
+    }
+
+    /// The number of draws the account had tickets in since its last update.
+    pub(crate) fn internal_new_draws(&self, account_id: &AccountId) -> u64 {
+        match self.loyalty_accounts.get(account_id) {
+            Some(loyalty_account)
+                if self.internal_get_account(account_id.clone()).tickets_amount > 0 =>
+            {
+                self.next_draw_id.saturating_sub(loyalty_account.draw_id)
+            }
+            _ => 0,
+        }
+    }
+
+    pub(crate) fn internal_loyalty_points(&self, account_id: &AccountId) -> u64 {
+        let account = self.internal_get_account(account_id.clone());
+        let loyalty_account = match self.loyalty_accounts.get(account_id) {
+            Some(loyalty_account) => loyalty_account,
+            // The stake points of the accounts from before the loyalty program carry over.
+            None => return account.stake_points,
+        };
+        let rules = &self.loyalty_rules;
+        let mut points = loyalty_account.points;
+        if account.staked_balance > 0 {
+            let epochs = env::epoch_height().saturating_sub(loyalty_account.epoch_height);
+            let amount_units = (account.staked_balance / rules.amount_unit) as u64;
+            let points_per_epoch = rules
+                .points_per_epoch
+                .saturating_add(amount_units.saturating_mul(rules.points_per_amount_unit));
+            points = points.saturating_add(epochs.saturating_mul(points_per_epoch));
+        }
+        if account.tickets_amount > 0 {
+            let draws = self.next_draw_id.saturating_sub(loyalty_account.draw_id);
+            points = points.saturating_add(draws.saturating_mul(rules.points_per_draw));
+        }
+        points
+    }
+}
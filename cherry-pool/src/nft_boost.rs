@@ -0,0 +1,477 @@
+use crate::*;
+use crate::internal::ext_nft;
+use near_contract_standards::non_fungible_token::core::NonFungibleTokenReceiver;
+use near_sdk::Gas;
+
+/// The multiplier of the tickets without a boost, in basis points.
+pub const NO_BOOST_MULTIPLIER_BPS: u32 = 10_000;
+/// The highest multiplier the owner can give to a rarity, 10×.
+const MAX_NFT_MULTIPLIER_BPS: u32 = 100_000;
+const GAS_FOR_NFT_TOKEN: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_NFT_BOOST_TOKEN: Gas = 30_000_000_000_000;
+const GAS_FOR_NFT_TRANSFER: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_NFT_BOOST_RETURNED: Gas = 10_000_000_000_000;
+const ONE_YOCTO: Balance = 1;
+const NO_DEPOSIT: Balance = 0;
+
+/// The tickets multiplier of the NFTs of a rarity.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftMultiplier {
+    /// The `rarity` attribute in the `extra` of the token metadata, e.g. `{"rarity":"legend"}`.
+    pub rarity: String,
+    /// Matches the tokens minted without the `rarity` attribute by their title.
+    pub title: String,
+    /// The multiplier in basis points, `15_000` is 1.5×.
+    pub multiplier_bps: u32,
+}
+
+/// The NFT boosting the tickets of an account.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftBoost {
+    pub token_id: TokenId,
+    pub rarity: String,
+    pub multiplier_bps: u32,
+    /// The tickets added on top of the tickets of the staked balance.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub bonus_tickets: u64,
+}
+
+/// An NFT held by the pool for the account it boosts.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakedNft {
+    pub token_id: TokenId,
+    pub account_id: AccountId,
+    pub multiplier_bps: u32,
+}
+
+/// The `msg` of an `nft_transfer_call` to the pool.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NftTransferMessage {
+    #[serde(default)]
+    boost: bool,
+    /// The rarity of a loyalty reward NFT stocked by the owner.
+    #[serde(default)]
+    reward: Option<String>,
+}
+
+/// The attributes in the `extra` of the token metadata.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NftAttributes {
+    rarity: Option<String>,
+}
+
+/// The multipliers of the `owner_mint_collection` editions.
+pub(crate) fn default_nft_multipliers() -> Vec<NftMultiplier> {
+    vec![
+        NftMultiplier {
+            rarity: "legend".to_string(),
+            title: "Cherry Wine".to_string(),
+            multiplier_bps: 50_000,
+        },
+        NftMultiplier {
+            rarity: "rare".to_string(),
+            title: "Cherry Cocktail".to_string(),
+            multiplier_bps: 30_000,
+        },
+        NftMultiplier {
+            rarity: "common".to_string(),
+            title: "Cherry Cake".to_string(),
+            multiplier_bps: 15_000,
+        },
+    ]
+}
+
+pub(crate) fn assert_valid_nft_multipliers(nft_multipliers: &[NftMultiplier]) {
+    for (index, nft_multiplier) in nft_multipliers.iter().enumerate() {
+        assert!(!nft_multiplier.rarity.is_empty(), "The rarity can't be empty");
+        assert!(
+            nft_multiplier.multiplier_bps >= NO_BOOST_MULTIPLIER_BPS
+                && nft_multiplier.multiplier_bps <= MAX_NFT_MULTIPLIER_BPS,
+            "The multiplier should be between {} and {} basis points",
+            NO_BOOST_MULTIPLIER_BPS,
+            MAX_NFT_MULTIPLIER_BPS
+        );
+        assert!(
+            nft_multipliers[..index]
+                .iter()
+                .all(|other| other.rarity != nft_multiplier.rarity),
+            "The rarity {} is listed twice",
+            nft_multiplier.rarity
+        );
+    }
+}
+
+/// The bonus tickets the multiplier adds to the given tickets, rounded down.
+fn nft_bonus_tickets(tickets: u64, multiplier_bps: u32) -> u64 {
+    (u128::from(tickets) * u128::from(multiplier_bps - NO_BOOST_MULTIPLIER_BPS)
+        / u128::from(NO_BOOST_MULTIPLIER_BPS)) as u64
+}
+
+/// Accounts boost their tickets by transferring an NFT of `nft_contract_id` to the pool with
+/// `nft_transfer_call` and the `{"boost":true}` message. The boost applies to the previous owner
+/// of the token, which has to be registered. The multiplier depends on the rarity of the token.
+/// The pool holds the token for the account until it's withdrawn with `withdraw_nft_boost` or
+/// `unstake_all`.
+///
+/// The owner stocks the loyalty rewards with the `{"reward":"<rarity>"}` message instead.
+#[near_bindgen]
+impl NonFungibleTokenReceiver for StakingPool {
+    /// Looks up the metadata of the token, `on_nft_boost_token` decides whether the pool keeps it.
+    /// A panic makes the NFT contract return the token.
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: TokenId,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.nft_contract_id,
+            "Only NFTs of {} can boost tickets",
+            self.nft_contract_id
+        );
+        let message: NftTransferMessage = near_sdk::serde_json::from_str(&msg)
+            .unwrap_or_else(|_| env::panic(b"Unknown NFT transfer message"));
+        if let Some(rarity) = message.reward {
+            assert_eq!(
+                previous_owner_id, self.owner_id,
+                "Only the owner can stock reward NFTs"
+            );
+            self.internal_stock_reward_nft(rarity, token_id);
+            return PromiseOrValue::Value(false);
+        }
+        assert!(message.boost, "Unknown NFT transfer message");
+        self.assert_not_emergency();
+        self.assert_registered(&previous_owner_id);
+        assert!(
+            self.nft_boosts.get(&previous_owner_id).is_none(),
+            "The account already has an NFT boost"
+        );
+        log!("@{} transferred NFT {} of @{}", sender_id, token_id, previous_owner_id);
+
+        ext_nft::nft_token(
+            token_id.clone(),
+            &self.nft_contract_id,
+            NO_DEPOSIT,
+            GAS_FOR_NFT_TOKEN,
+        )
+        .then(ext_self::on_nft_boost_token(
+            previous_owner_id,
+            token_id,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_ON_NFT_BOOST_TOKEN,
+        ))
+        .into()
+    }
+}
+
+#[near_bindgen]
+impl StakingPool {
+    /// Callback of looking up a boost NFT. Boosts the tickets of the account with the multiplier
+    /// of the token's rarity and returns `false` to keep the token. Returns `true` to send the
+    /// token back when it has no known rarity or the account can't be boosted anymore.
+    pub fn on_nft_boost_token(&mut self, account_id: AccountId, token_id: TokenId) -> bool {
+        assert_eq!(
+            env::current_account_id(),
+            env::predecessor_account_id(),
+            "Can be called only as a callback"
+        );
+        let token = match env::promise_result(0) {
+            PromiseResult::Successful(result) => {
+                near_sdk::serde_json::from_slice::<Option<Token>>(&result)
+                    .ok()
+                    .flatten()
+            }
+            _ => None,
+        };
+        let nft_multiplier = match token.and_then(|token| self.nft_multiplier_of(&token)) {
+            Some(nft_multiplier) => nft_multiplier,
+            None => {
+                log!("NFT {} has no boost rarity, returning it to @{}", token_id, account_id);
+                return true;
+            }
+        };
+        if self.is_emergency || self.nft_boosts.get(&account_id).is_some() {
+            log!("@{} can't be boosted, returning NFT {}", account_id, token_id);
+            return true;
+        }
+
+        self.internal_ping();
+        self.internal_apply_nft_boost(&account_id, token_id, nft_multiplier);
+        false
+    }
+
+    /// Removes the NFT boost of the predecessor with its bonus tickets and sends the NFT back.
+    pub fn withdraw_nft_boost(&mut self) {
+        let account_id = env::predecessor_account_id();
+        assert!(
+            self.nft_boosts.contains_key(&account_id),
+            "The account has no NFT boost"
+        );
+        self.internal_ping();
+        self.internal_return_nft_boost(&account_id);
+    }
+
+    /// Callback of sending a boost NFT back. The token of a failed transfer stays in the pool and
+    /// boosts the account again. Its bonus tickets come back right away, or with the next ticket
+    /// change of the account while a draw is pending. If the account is boosted by another NFT
+    /// meanwhile, the token waits for `withdraw_unreturned_nft` instead.
+    pub fn on_nft_boost_returned(&mut self, account_id: AccountId, nft_boost: NftBoost) {
+        assert_eq!(
+            env::current_account_id(),
+            env::predecessor_account_id(),
+            "Can be called only as a callback"
+        );
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            self.nft_depositors.remove(&nft_boost.token_id);
+            return;
+        }
+        log!(
+            "Returning NFT {} to @{} failed, it's kept for the account",
+            nft_boost.token_id,
+            account_id
+        );
+        // An unregistered account can't be boosted again, and a boosted one keeps its other boost.
+        if !self.is_registered(&account_id) || self.nft_boosts.contains_key(&account_id) {
+            self.internal_add_unreturned_nft(&account_id, nft_boost.token_id);
+            return;
+        }
+        self.nft_boosts.insert(
+            &account_id,
+            &NftBoost {
+                bonus_tickets: 0,
+                ..nft_boost
+            },
+        );
+        if self.pending_draw.is_none() {
+            self.internal_update_nft_bonus(&account_id);
+        }
+    }
+
+    /// Sends back an NFT of the predecessor that failed to be returned while another NFT boosted
+    /// its tickets.
+    pub fn withdraw_unreturned_nft(&mut self, token_id: TokenId) {
+        let account_id = env::predecessor_account_id();
+        let mut token_ids = self.unreturned_nfts.get(&account_id).unwrap_or_default();
+        let index = token_ids
+            .iter()
+            .position(|unreturned_token_id| unreturned_token_id == &token_id)
+            .unwrap_or_else(|| env::panic(format!("The NFT {} isn't waiting to be returned", token_id).as_bytes()));
+        token_ids.swap_remove(index);
+        if token_ids.is_empty() {
+            self.unreturned_nfts.remove(&account_id);
+        } else {
+            self.unreturned_nfts.insert(&account_id, &token_ids);
+        }
+
+        self.last_total_balance -= ONE_YOCTO;
+        ext_nft::nft_transfer(
+            account_id.clone(),
+            token_id.clone(),
+            None,
+            None,
+            &self.nft_contract_id,
+            ONE_YOCTO,
+            GAS_FOR_NFT_TRANSFER,
+        )
+        .then(ext_self::on_unreturned_nft_sent(
+            account_id,
+            token_id,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_ON_NFT_BOOST_RETURNED,
+        ));
+    }
+
+    /// Callback of sending back an unreturned NFT. The token of a failed transfer can be withdrawn
+    /// again.
+    pub fn on_unreturned_nft_sent(&mut self, account_id: AccountId, token_id: TokenId) {
+        assert_eq!(
+            env::current_account_id(),
+            env::predecessor_account_id(),
+            "Can be called only as a callback"
+        );
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            self.nft_depositors.remove(&token_id);
+            return;
+        }
+        log!("Returning NFT {} to @{} failed, it can be withdrawn again", token_id, account_id);
+        self.internal_add_unreturned_nft(&account_id, token_id);
+    }
+
+    /// Returns the NFTs of the given account that failed to be returned and can be withdrawn.
+    pub fn get_account_unreturned_nfts(&self, account_id: ValidAccountId) -> Vec<TokenId> {
+        self.unreturned_nfts.get(account_id.as_ref()).unwrap_or_default()
+    }
+
+    /// Owner's method.
+    /// Sets the tickets multipliers of the NFT rarities. They apply to the boosts made after.
+    pub fn set_nft_multipliers(&mut self, nft_multipliers: Vec<NftMultiplier>) {
+        self.assert_owner();
+        assert_valid_nft_multipliers(&nft_multipliers);
+        self.nft_multipliers = nft_multipliers;
+    }
+
+    pub fn get_nft_multipliers(&self) -> Vec<NftMultiplier> {
+        self.nft_multipliers.clone()
+    }
+
+    /// Returns the NFT boosting the tickets of the given account, if any.
+    pub fn get_account_nft_boost(&self, account_id: ValidAccountId) -> Option<NftBoost> {
+        self.nft_boosts.get(account_id.as_ref())
+    }
+
+    pub fn get_number_of_staked_nfts(&self) -> U64 {
+        self.nft_depositors.len().into()
+    }
+
+    /// Returns the NFTs the pool holds for the accounts they boost, from the given index.
+    pub fn get_staked_nfts(&self, from_index: U64, limit: U64) -> Vec<StakedNft> {
+        let from_index: u64 = from_index.into();
+        let limit: u64 = limit.into();
+        let token_ids = self.nft_depositors.keys_as_vector();
+        let account_ids = self.nft_depositors.values_as_vector();
+        (from_index..std::cmp::min(from_index + limit, token_ids.len()))
+            .map(|index| {
+                let token_id = token_ids.get(index).unwrap();
+                let account_id = account_ids.get(index).unwrap();
+                let multiplier_bps = self
+                    .nft_boosts
+                    .get(&account_id)
+                    .filter(|nft_boost| nft_boost.token_id == token_id)
+                    .map_or(NO_BOOST_MULTIPLIER_BPS, |nft_boost| nft_boost.multiplier_bps);
+                StakedNft {
+                    token_id,
+                    account_id,
+                    multiplier_bps,
+                }
+            })
+            .collect()
+    }
+}
+
+impl StakingPool {
+    /// Finds the multiplier of the token by its `rarity` attribute, or by its title if it has none.
+    fn nft_multiplier_of(&self, token: &Token) -> Option<NftMultiplier> {
+        let metadata = token.metadata.as_ref()?;
+        let rarity = metadata
+            .extra
+            .as_ref()
+            .and_then(|extra| near_sdk::serde_json::from_str::<NftAttributes>(extra).ok())
+            .and_then(|attributes| attributes.rarity);
+        self.nft_multipliers
+            .iter()
+            .find(|nft_multiplier| match &rarity {
+                Some(rarity) => &nft_multiplier.rarity == rarity,
+                None => metadata.title.as_ref() == Some(&nft_multiplier.title),
+            })
+            .cloned()
+    }
+
+    /// Records the deposited token and adds the bonus tickets of the multiplier on top of the
+    /// tickets of the account.
+    fn internal_apply_nft_boost(
+        &mut self,
+        account_id: &AccountId,
+        token_id: TokenId,
+        nft_multiplier: NftMultiplier,
+    ) {
+        self.nft_boosts.insert(
+            account_id,
+            &NftBoost {
+                token_id: token_id.clone(),
+                rarity: nft_multiplier.rarity,
+                multiplier_bps: nft_multiplier.multiplier_bps,
+                bonus_tickets: 0,
+            },
+        );
+        self.internal_update_nft_bonus(account_id);
+        self.nft_depositors.insert(&token_id, account_id);
+
+        PoolEvent::NftBoostApplied(vec![NftData {
+            account_id: account_id.clone(),
+            token_id,
+        }])
+        .emit();
+    }
+
+    fn internal_add_unreturned_nft(&mut self, account_id: &AccountId, token_id: TokenId) {
+        let mut token_ids = self.unreturned_nfts.get(account_id).unwrap_or_default();
+        token_ids.push(token_id);
+        self.unreturned_nfts.insert(account_id, &token_ids);
+    }
+
+    /// The bonus tickets of the account's NFT boost it still holds.
+    pub(crate) fn internal_nft_bonus_tickets(&self, account: &StakingPoolAccount) -> u64 {
+        self.nft_boosts
+            .get(&account.account_id)
+            .map_or(0, |nft_boost| std::cmp::min(nft_boost.bonus_tickets, account.tickets_amount))
+    }
+
+    /// Recomputes the bonus tickets of the account's NFT boost, if it has one, from the rest of
+    /// its tickets and adds or removes the difference.
+    pub(crate) fn internal_update_nft_bonus(&mut self, account_id: &AccountId) {
+        let mut nft_boost = match self.nft_boosts.get(account_id) {
+            Some(nft_boost) => nft_boost,
+            None => return,
+        };
+        let account = self.internal_get_account(account_id.clone());
+        let bonus_tickets = self.internal_nft_bonus_tickets(&account);
+        let new_bonus_tickets = nft_bonus_tickets(
+            account.tickets_amount - bonus_tickets,
+            nft_boost.multiplier_bps,
+        );
+        if new_bonus_tickets > bonus_tickets {
+            self.internal_give_tickets(account_id, new_bonus_tickets - bonus_tickets);
+        } else if new_bonus_tickets < bonus_tickets {
+            self.internal_take_tickets(account, bonus_tickets - new_bonus_tickets);
+        }
+        nft_boost.bonus_tickets = new_bonus_tickets;
+        self.nft_boosts.insert(account_id, &nft_boost);
+    }
+
+    /// Removes the NFT boost of the account with its bonus tickets, if it has one, and sends the
+    /// NFT back to the account.
+    pub(crate) fn internal_return_nft_boost(&mut self, account_id: &AccountId) {
+        let nft_boost = match self.nft_boosts.remove(account_id) {
+            Some(nft_boost) => nft_boost,
+            None => return,
+        };
+        self.assert_tickets_unlocked();
+        let account = self.internal_get_account(account_id.clone());
+        // The bonus tickets are already gone if all the tickets of the account were removed.
+        let bonus_tickets = std::cmp::min(nft_boost.bonus_tickets, account.tickets_amount);
+        self.internal_take_tickets(account, bonus_tickets);
+
+        PoolEvent::NftBoostRemoved(vec![NftData {
+            account_id: account_id.clone(),
+            token_id: nft_boost.token_id.clone(),
+        }])
+        .emit();
+
+        self.last_total_balance -= ONE_YOCTO;
+        ext_nft::nft_transfer(
+            account_id.clone(),
+            nft_boost.token_id.clone(),
+            None,
+            None,
+            &self.nft_contract_id,
+            ONE_YOCTO,
+            GAS_FOR_NFT_TRANSFER,
+        )
+        .then(ext_self::on_nft_boost_returned(
+            account_id.clone(),
+            nft_boost,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_ON_NFT_BOOST_RETURNED,
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+use crate::*;
+
+/// What is currently paused in the pool.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PauseStatus {
+    pub staking: bool,
+    pub deposits: bool,
+    pub draws: bool,
+    pub emergency: bool,
+}
+
+#[near_bindgen]
+impl StakingPool {
+    /// Owner's, operator's or guardian's method.
+    /// Pauses staking, the staked balance is unstaked from the validator.
+    pub fn pause_staking(&mut self) {
+        self.assert_owner_or_role(&[Role::Operator, Role::Guardian]);
+        assert!(!self.is_restake_paused, "The staking is already paused");
+
+        self.internal_ping();
+        self.is_restake_paused = true;
+        if self.is_native_staking() {
+            Promise::new(env::current_account_id()).stake(0, self.stake_public_key.clone());
+        }
+    }
+
+    /// Owner's or operator's method.
+    /// Resumes staking and restakes the staked balance.
+    pub fn resume_staking(&mut self) {
+        self.assert_owner_or_role(&[Role::Operator]);
+        assert!(self.is_restake_paused, "The staking is not paused");
+
+        self.internal_ping();
+        self.is_restake_paused = false;
+        self.internal_restake();
+    }
+
+    /// Owner's, operator's or guardian's method.
+    /// Stops new deposits, unstaking and withdrawing keep working.
+    pub fn pause_deposits(&mut self) {
+        self.assert_owner_or_role(&[Role::Operator, Role::Guardian]);
+        self.is_deposit_paused = true;
+    }
+
+    /// Owner's or operator's method.
+    pub fn resume_deposits(&mut self) {
+        self.assert_owner_or_role(&[Role::Operator]);
+        self.is_deposit_paused = false;
+    }
+
+    /// Owner's, operator's or guardian's method.
+    /// Stops new prize draws, the prize pot keeps growing until they are resumed. A committed
+    /// draw stays and can still be revealed, cancelling it would let the draw be rolled again.
+    pub fn pause_draws(&mut self) {
+        self.assert_owner_or_role(&[Role::Operator, Role::Guardian]);
+        self.is_draw_paused = true;
+    }
+
+    /// Owner's or operator's method.
+    pub fn resume_draws(&mut self) {
+        self.assert_owner_or_role(&[Role::Operator]);
+        self.is_draw_paused = false;
+    }
+
+    /// Owner's method.
+    /// Switches the pool to the emergency mode: accounts can only unstake and withdraw. Nothing
+    /// can be deposited or staked, no draws happen and no prizes can be claimed. A committed draw
+    /// is cancelled so the tickets can be removed.
+    pub fn enter_emergency_mode(&mut self) {
+        self.assert_owner();
+        assert!(!self.is_emergency, "The pool is already in the emergency mode");
+        self.is_emergency = true;
+        self.internal_cancel_pending_draw();
+        log!("The pool entered the emergency mode");
+    }
+
+    /// Owner's method.
+    pub fn exit_emergency_mode(&mut self) {
+        self.assert_owner();
+        assert!(self.is_emergency, "The pool isn't in the emergency mode");
+        self.is_emergency = false;
+        log!("The pool exited the emergency mode");
+    }
+
+    /// Owner's method.
+    /// Rotates the validator staking key and restakes with it.
+    pub fn update_staking_key(&mut self, stake_public_key: Base58PublicKey) {
+        self.assert_owner();
+        // When updating the staking key, the contract has to restake.
+        let _need_to_restake = self.internal_ping();
+        self.stake_public_key = stake_public_key.into();
+        self.internal_restake();
+    }
+
+    pub fn get_pause_status(&self) -> PauseStatus {
+        PauseStatus {
+            staking: self.is_restake_paused,
+            deposits: self.is_deposit_paused,
+            draws: self.is_draw_paused,
+            emergency: self.is_emergency,
+        }
+    }
+}
+
+impl StakingPool {
+    pub(crate) fn assert_deposits_allowed(&self) {
+        self.assert_not_emergency();
+        assert!(!self.is_deposit_paused, "Deposits are paused");
+    }
+
+    pub(crate) fn assert_not_emergency(&self) {
+        assert!(
+            !self.is_emergency,
+            "The pool is in the emergency mode, only unstaking and withdrawing are allowed"
+        );
+    }
+
+    fn internal_cancel_pending_draw(&mut self) {
+        if let Some(pending_draw) = self.pending_draw.take() {
+            // The pending draw always has the latest draw id.
+            self.next_draw_id = pending_draw.draw_id;
+            log!("Draw {}: cancelled", pending_draw.draw_id);
+        }
+    }
+
+    /// Whether draws can happen now. They wait for the legacy tickets to be migrated too.
+    pub(crate) fn are_draws_active(&self) -> bool {
+        !self.is_draw_paused && !self.is_emergency && self.legacy_ticket_slots.is_none()
+    }
+}
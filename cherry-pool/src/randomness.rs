@@ -0,0 +1,161 @@
+use crate::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use std::convert::TryInto;
+
+/// Sequential identifier of a prize draw.
+pub type DrawId = u64;
+
+/// Randomness inputs recorded for a draw, enough for anyone to recompute its winning tickets.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DrawSeed {
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub draw_id: DrawId,
+    /// The epoch height at which the draw happened.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub epoch_height: EpochHeight,
+    /// The block `random_seed` the draw seed was derived from.
+    pub random_seed: Vec<u8>,
+    /// `sha256(random_seed || epoch_height)` of the block the draw was revealed at.
+    pub seed_commitment: Vec<u8>,
+    /// The number of time-weighted tickets in play at the moment of the draw.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub tickets_count: u64,
+    /// The number of distinct tickets drawn, one per prize tier.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub winners_count: u64,
+    /// The winning tickets in prize tier order.
+    #[serde(with = "crate::serialize::vec_u64_dec_format")]
+    pub winning_tickets: Vec<u64>,
+}
+
+impl DrawSeed {
+    pub fn new(
+        random_seed: Vec<u8>,
+        draw_id: DrawId,
+        epoch_height: EpochHeight,
+        tickets_count: u64,
+        winners_count: u64,
+    ) -> Self {
+        let seed = derive_draw_seed(&random_seed, draw_id, epoch_height);
+        Self {
+            draw_id,
+            epoch_height,
+            seed_commitment: seed_commitment(&random_seed, epoch_height),
+            random_seed,
+            tickets_count,
+            winners_count,
+            winning_tickets: draw_distinct_tickets(seed, tickets_count, winners_count),
+        }
+    }
+
+    /// Checks that the stored seed inputs are the ones the draw was revealed with.
+    pub fn assert_commitment(&self) {
+        assert_eq!(
+            seed_commitment(&self.random_seed, self.epoch_height),
+            self.seed_commitment,
+            "The draw seed doesn't match its commitment"
+        );
+    }
+
+    /// Recomputes the winning tickets from the stored inputs, checking them against the stored
+    /// seed commitment first.
+    pub fn compute_winning_tickets(&self) -> Vec<u64> {
+        self.assert_commitment();
+        let seed = derive_draw_seed(&self.random_seed, self.draw_id, self.epoch_height);
+        draw_distinct_tickets(seed, self.tickets_count, self.winners_count)
+    }
+}
+
+/// A commit-reveal draw that has locked its ticket snapshot and waits for its reveal block.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingDraw {
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub draw_id: DrawId,
+    /// The number of time-weighted tickets locked in at commit time.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub tickets_count: u64,
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub commit_block_height: BlockHeight,
+    /// The first block at which the draw can be revealed. Its `random_seed` doesn't exist yet at
+    /// commit time.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub reveal_block_height: BlockHeight,
+    /// `sha256(draw_id || tickets_count || reveal_block_height)`.
+    pub commitment: Vec<u8>,
+}
+
+impl PendingDraw {
+    pub fn new(draw_id: DrawId, tickets_count: u64, reveal_block_height: BlockHeight) -> Self {
+        Self {
+            draw_id,
+            tickets_count,
+            commit_block_height: env::block_index(),
+            reveal_block_height,
+            commitment: draw_commitment(draw_id, tickets_count, reveal_block_height),
+        }
+    }
+
+    /// Checks that the draw can be revealed now and that the ticket snapshot is intact.
+    pub fn assert_revealable(&self, tickets_count: u64) {
+        assert!(
+            env::block_index() >= self.reveal_block_height,
+            "The draw can't be revealed before block {}",
+            self.reveal_block_height
+        );
+        assert_eq!(
+            draw_commitment(self.draw_id, tickets_count, self.reveal_block_height),
+            self.commitment,
+            "The ticket snapshot doesn't match the draw commitment"
+        );
+    }
+}
+
+pub(crate) fn draw_commitment(draw_id: DrawId, tickets_count: u64, reveal_block_height: BlockHeight) -> Vec<u8> {
+    let mut input = draw_id.to_le_bytes().to_vec();
+    input.extend_from_slice(&tickets_count.to_le_bytes());
+    input.extend_from_slice(&reveal_block_height.to_le_bytes());
+    env::sha256(&input)
+}
+
+/// Derives the seed of a draw as `sha256(random_seed || draw_id || epoch_height)`, with the
+/// integers encoded as little-endian bytes.
+pub(crate) fn derive_draw_seed(random_seed: &[u8], draw_id: DrawId, epoch_height: EpochHeight) -> [u8; 32] {
+    let mut input = random_seed.to_vec();
+    input.extend_from_slice(&draw_id.to_le_bytes());
+    input.extend_from_slice(&epoch_height.to_le_bytes());
+    env::sha256(&input).try_into().unwrap()
+}
+
+pub(crate) fn seed_commitment(random_seed: &[u8], epoch_height: EpochHeight) -> Vec<u8> {
+    let mut input = random_seed.to_vec();
+    input.extend_from_slice(&epoch_height.to_le_bytes());
+    env::sha256(&input)
+}
+
+/// Deterministically picks `winners_count` distinct tickets out of `tickets_count` using `seed`.
+/// Tickets are drawn without replacement: every draw picks uniformly among the tickets that
+/// haven't won yet.
+pub(crate) fn draw_distinct_tickets(seed: [u8; 32], tickets_count: u64, winners_count: u64) -> Vec<u64> {
+    assert!(
+        winners_count <= tickets_count,
+        "Can't draw more winners than there are tickets"
+    );
+    let mut rng = ChaCha8Rng::from_seed(seed);
+    let mut tickets: Vec<u64> = Vec::with_capacity(winners_count as usize);
+    for i in 0..winners_count {
+        let mut ticket = rng.gen_range(0..tickets_count - i);
+        // Skip over the tickets that already won, in ascending order.
+        let mut drawn = tickets.clone();
+        drawn.sort_unstable();
+        for drawn_ticket in drawn {
+            if ticket >= drawn_ticket {
+                ticket += 1;
+            }
+        }
+        tickets.push(ticket);
+    }
+    tickets
+}
@@ -0,0 +1,58 @@
+//! JSON formats of the integer fields of the stored structs. They keep their native types in the
+//! state and in the code, and are encoded in JSON as base-10 strings like `U128` and `U64`, so
+//! JavaScript clients don't lose precision.
+
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+pub mod u64_dec_format {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+        U64(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        U64::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+pub mod u128_dec_format {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        U128(*value).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        U128::deserialize(deserializer).map(|value| value.0)
+    }
+}
+
+pub mod option_u128_dec_format {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &Option<u128>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.map(U128).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u128>, D::Error> {
+        Option::<U128>::deserialize(deserializer).map(|value| value.map(|value| value.0))
+    }
+}
+
+pub mod vec_u64_dec_format {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(values: &[u64], serializer: S) -> Result<S::Ok, S::Error> {
+        values
+            .iter()
+            .map(|value| U64(*value))
+            .collect::<Vec<_>>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u64>, D::Error> {
+        Vec::<U64>::deserialize(deserializer).map(|values| values.into_iter().map(|value| value.0).collect())
+    }
+}
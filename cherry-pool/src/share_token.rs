@@ -0,0 +1,199 @@
+use crate::*;
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::fungible_token::metadata::{
+    FungibleTokenMetadata, FungibleTokenMetadataProvider, FT_METADATA_SPEC,
+};
+use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+use near_sdk::{assert_one_yocto, Gas};
+use std::cmp::min;
+
+const GAS_FOR_RESOLVE_TRANSFER: Gas = 5_000_000_000_000;
+const GAS_FOR_FT_TRANSFER_CALL: Gas = 25_000_000_000_000 + GAS_FOR_RESOLVE_TRANSFER;
+const NO_DEPOSIT: Balance = 0;
+
+#[ext_contract(ext_share_receiver)]
+pub trait ShareReceiver {
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128>;
+}
+
+/// The pool stake shares are exposed as a NEP-141 token. Balances are the `stake_shares` of the
+/// accounts and the tickets follow the shares when they move.
+#[near_bindgen]
+impl FungibleTokenCore for StakingPool {
+    #[payable]
+    fn ft_transfer(&mut self, receiver_id: ValidAccountId, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        self.assert_tickets_unlocked();
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer_shares(&sender_id, receiver_id.as_ref(), amount.into(), memo);
+    }
+
+    #[payable]
+    fn ft_transfer_call(
+        &mut self,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        self.assert_tickets_unlocked();
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer_shares(&sender_id, receiver_id.as_ref(), amount.into(), memo);
+        self.pending_share_transfers += 1;
+        ext_share_receiver::ft_on_transfer(
+            sender_id.clone(),
+            amount,
+            msg,
+            receiver_id.as_ref(),
+            NO_DEPOSIT,
+            env::prepaid_gas() - GAS_FOR_FT_TRANSFER_CALL,
+        )
+        .then(ext_self::ft_resolve_transfer(
+            sender_id,
+            receiver_id.into(),
+            amount,
+            &env::current_account_id(),
+            NO_DEPOSIT,
+            GAS_FOR_RESOLVE_TRANSFER,
+        ))
+        .into()
+    }
+
+    /// The shares minted at the initialization with the `STAKE_SHARE_PRICE_GUARANTEE_FUND` belong
+    /// to the pool itself, so the supply is a bit more than the sum of the account balances.
+    fn ft_total_supply(&self) -> U128 {
+        self.total_stake_shares.into()
+    }
+
+    fn ft_balance_of(&self, account_id: ValidAccountId) -> U128 {
+        self.internal_get_account(account_id.into()).stake_shares.into()
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenResolver for StakingPool {
+    /// Returns the amount of shares used by the receiver, the unused shares are refunded to the
+    /// sender with their tickets. No draw is committed while the transfer is in flight, so the
+    /// tickets aren't locked.
+    fn ft_resolve_transfer(
+        &mut self,
+        sender_id: ValidAccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+    ) -> U128 {
+        assert_eq!(
+            env::current_account_id(),
+            env::predecessor_account_id(),
+            "Can be called only as a callback"
+        );
+        self.pending_share_transfers = self.pending_share_transfers.saturating_sub(1);
+        let amount: Balance = amount.into();
+        let unused_amount = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => {
+                if let Ok(unused_amount) = near_sdk::serde_json::from_slice::<U128>(&value) {
+                    min(amount, unused_amount.0)
+                } else {
+                    amount
+                }
+            }
+            PromiseResult::Failed => amount,
+        };
+
+        let receiver_id: AccountId = receiver_id.into();
+        // The shares of a sender that unregistered meanwhile stay with the receiver.
+        let refund_amount = if self.is_registered(sender_id.as_ref()) {
+            min(self.internal_get_account(receiver_id.clone()).stake_shares, unused_amount)
+        } else {
+            0
+        };
+        if refund_amount > 0 {
+            self.internal_transfer_shares(&receiver_id, sender_id.as_ref(), refund_amount, None);
+        }
+        U128(amount - refund_amount)
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenMetadataProvider for StakingPool {
+    fn ft_metadata(&self) -> FungibleTokenMetadata {
+        FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "Cherry Pool Share".to_string(),
+            symbol: "CHERRY".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            // A share is worth one yoctoNEAR at the initialization.
+            decimals: 24,
+        }
+    }
+}
+
+impl StakingPool {
+    /// Moves `amount` stake shares with the proportional part of the sender's tickets, leaving out
+    /// the bonus tickets of its NFT boost. The NFT boosts stay with their accounts, their bonus
+    /// tickets are recomputed for the moved tickets.
+    pub(crate) fn internal_transfer_shares(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        assert_ne!(sender_id, receiver_id, "Sender and receiver should be different");
+        assert!(amount > 0, "The amount should be a positive number");
+        self.assert_registered(receiver_id);
+
+        self.internal_update_loyalty_points(sender_id);
+        self.internal_update_loyalty_points(receiver_id);
+        let mut sender = self.internal_get_account(sender_id.clone());
+        let mut receiver = self.internal_get_account(receiver_id.clone());
+        assert!(
+            sender.stake_shares >= amount,
+            "The account doesn't have enough balance"
+        );
+        let sender_tickets = sender.tickets_amount - self.internal_nft_bonus_tickets(&sender);
+        let tickets_num = if amount == sender.stake_shares {
+            sender_tickets
+        } else {
+            (U256::from(sender_tickets) * U256::from(amount) / U256::from(sender.stake_shares))
+                .as_u64()
+        };
+
+        sender.stake_shares -= amount;
+        receiver.stake_shares += amount;
+        if tickets_num > 0 {
+            sender.tickets_amount -= tickets_num;
+            receiver.tickets_amount += tickets_num;
+            self.tickets.remove(sender_id, tickets_num);
+            self.tickets.add(receiver_id, tickets_num);
+        }
+        self.save_account(&sender);
+        self.save_account(&receiver);
+        PoolEvent::FtTransfer(vec![FtTransferData {
+            old_owner_id: sender_id.clone(),
+            new_owner_id: receiver_id.clone(),
+            amount: amount.into(),
+            memo,
+        }])
+        .emit();
+        if tickets_num > 0 {
+            PoolEvent::TicketsRemoved(vec![TicketsData {
+                account_id: sender.account_id,
+                tickets: tickets_num.into(),
+                total_tickets: sender.tickets_amount.into(),
+            }])
+            .emit();
+            PoolEvent::TicketsAdded(vec![TicketsData {
+                account_id: receiver.account_id,
+                tickets: tickets_num.into(),
+                total_tickets: receiver.tickets_amount.into(),
+            }])
+            .emit();
+        }
+        self.internal_update_nft_bonus(sender_id);
+        self.internal_update_nft_bonus(receiver_id);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::*;
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
+use near_sdk::{assert_one_yocto, StorageUsage};
+
+/// Price per 1 byte of storage from mainnet genesis config.
+pub const STORAGE_PRICE_PER_BYTE: Balance = 10_000_000_000_000_000_000;
+/// The `StakingPoolAccount` entry without its pending prizes.
+const ACCOUNT_STORAGE_USAGE: StorageUsage = 400;
+/// The ticket ledger slot of an account: its owner, its index and its tree node. A released slot
+/// is reused by the next account that gets one.
+const TICKET_SLOT_STORAGE_USAGE: StorageUsage = 200;
+/// The loyalty points record of an account.
+const LOYALTY_STORAGE_USAGE: StorageUsage = 100;
+/// The achievements record of an account. It's kept after the account is unregistered, so its
+/// NFTs can't be claimed twice, and it's paid for once.
+pub(crate) const ACHIEVEMENTS_STORAGE_USAGE: StorageUsage = 100;
+/// One pending prize of an account.
+const ACCOUNT_ENTRY_STORAGE_USAGE: StorageUsage = 16;
+
+/// Every account has to be registered with `storage_deposit` before it can deposit or receive
+/// pool shares. The storage balance is fixed, it covers the account, its ticket slot and the
+/// largest number of pending prizes it can have.
+#[near_bindgen]
+impl StorageManagement for StakingPool {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<ValidAccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        // The storage balance is fixed, so every deposit is registration only.
+        let _ = registration_only;
+        let amount = env::attached_deposit();
+        let account_id: AccountId = account_id
+            .map(|account_id| account_id.into())
+            .unwrap_or_else(env::predecessor_account_id);
+        if self.is_registered(&account_id) {
+            log!("The account is already registered, refunding the deposit");
+            if amount > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(amount);
+            }
+        } else {
+            let charge = self.registration_cost(&account_id);
+            assert!(
+                amount >= charge,
+                "The attached deposit is less than the minimum storage balance"
+            );
+            self.internal_register_account(&account_id);
+            // The storage deposit isn't a reward.
+            self.last_total_balance += charge;
+            let refund = amount - charge;
+            if refund > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(refund);
+            }
+        }
+        self.internal_storage_balance_of(&account_id).unwrap()
+    }
+
+    /// The storage balance is fixed, so there is never anything available to withdraw.
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let storage_balance = self
+            .internal_storage_balance_of(&account_id)
+            .unwrap_or_else(|| env::panic(format!("The account {} is not registered", account_id).as_bytes()));
+        if let Some(amount) = amount {
+            assert!(
+                amount.0 == 0,
+                "The amount is greater than the available storage balance"
+            );
+        }
+        storage_balance
+    }
+
+    /// Unregisters an account that holds nothing in the pool and refunds its storage deposit.
+    /// `force` isn't supported, pool balances can't be burned.
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        assert!(!force.unwrap_or(false), "Force unregistering isn't supported");
+        let account_id = env::predecessor_account_id();
+        if !self.is_registered(&account_id) {
+            log!("The account {} is not registered", account_id);
+            return false;
+        }
+        let account = self.internal_get_account(account_id.clone());
+        assert!(
+            is_account_empty(&account, self.prizes_expired_before_draw_id),
+            "Can't unregister the account with a positive balance"
+        );
+        assert!(
+            !self.nft_boosts.contains_key(&account_id),
+            "Can't unregister the account with an NFT boost, withdraw it first"
+        );
+        self.internal_unregister_account(&account_id);
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: self.account_storage_cost().into(),
+            max: Some(self.account_storage_cost().into()),
+        }
+    }
+
+    fn storage_balance_of(&self, account_id: ValidAccountId) -> Option<StorageBalance> {
+        self.internal_storage_balance_of(account_id.as_ref())
+    }
+}
+
+impl StakingPool {
+    /// The storage deposit of one account.
+    pub(crate) fn account_storage_cost(&self) -> Balance {
+        let max_entries = NUM_DRAWS_TO_CLAIM_PRIZE;
+        Balance::from(
+            ACCOUNT_STORAGE_USAGE
+                + TICKET_SLOT_STORAGE_USAGE
+                + LOYALTY_STORAGE_USAGE
+                + ACHIEVEMENTS_STORAGE_USAGE
+                + max_entries * ACCOUNT_ENTRY_STORAGE_USAGE,
+        ) * STORAGE_PRICE_PER_BYTE
+    }
+
+    /// The deposit charged to register the account. The achievements of an account that
+    /// registers again are still paid for.
+    pub(crate) fn registration_cost(&self, account_id: &AccountId) -> Balance {
+        self.account_storage_cost() - self.kept_achievements_storage_cost(account_id)
+    }
+
+    /// Registers the account, its storage balance is the full storage deposit no matter what it
+    /// was charged.
+    pub(crate) fn internal_register_account(&mut self, account_id: &AccountId) {
+        self.save_account(&StakingPoolAccount::new(account_id));
+        self.storage_deposits.insert(account_id, &self.account_storage_cost());
+    }
+
+    pub(crate) fn assert_registered(&self, account_id: &AccountId) {
+        assert!(
+            self.is_registered(account_id),
+            "The account {} is not registered",
+            account_id
+        );
+    }
+
+    /// Removes the account once it has nothing left in the pool and refunds its storage deposit.
+    pub(crate) fn internal_remove_account_if_empty(&mut self, account_id: &AccountId) {
+        if let Some(account) = self.accounts.get(account_id) {
+            if is_account_empty(&account, self.prizes_expired_before_draw_id)
+                && !self.nft_boosts.contains_key(account_id)
+            {
+                self.internal_unregister_account(account_id);
+            }
+        }
+    }
+
+    fn internal_unregister_account(&mut self, account_id: &AccountId) {
+        self.accounts.remove(account_id);
+        if let Some(legacy_accounts) = self.legacy_accounts.as_mut() {
+            legacy_accounts.remove(account_id);
+        }
+        self.loyalty_accounts.remove(account_id);
+        self.tickets.release_slot(account_id);
+        let refund = self
+            .storage_deposits
+            .remove(account_id)
+            .unwrap_or(0)
+            .saturating_sub(self.kept_achievements_storage_cost(account_id));
+        log!("@{} unregistered", account_id);
+        if refund > 0 {
+            self.last_total_balance -= refund;
+            Promise::new(account_id.clone()).transfer(refund);
+        }
+    }
+
+    /// The storage of the achievements record the account keeps after it's unregistered.
+    fn kept_achievements_storage_cost(&self, account_id: &AccountId) -> Balance {
+        if self.account_achievements.contains_key(account_id) {
+            Balance::from(ACHIEVEMENTS_STORAGE_USAGE) * STORAGE_PRICE_PER_BYTE
+        } else {
+            0
+        }
+    }
+
+    fn internal_storage_balance_of(&self, account_id: &AccountId) -> Option<StorageBalance> {
+        if self.is_registered(account_id) {
+            Some(StorageBalance {
+                total: self.storage_deposits.get(account_id).unwrap_or(0).into(),
+                available: 0.into(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+/// Expired prizes are already back in the prize pot, so they don't keep the account.
+fn is_account_empty(account: &StakingPoolAccount, prizes_expired_before_draw_id: DrawId) -> bool {
+    account.stake_shares == 0
+        && account.unstaked_balance == 0
+        && account.tickets_amount == 0
+        && account
+            .pending_prizes
+            .iter()
+            .all(|prize| prize.draw_id < prizes_expired_before_draw_id)
+}
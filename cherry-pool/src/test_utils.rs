@@ -1,6 +1,8 @@
 use near_sdk::{AccountId, MockedBlockchain, PromiseResult, VMContext};
 use near_sdk::{Balance, BlockHeight, EpochHeight};
 
+use crate::RewardFeeFraction;
+
 pub fn staking() -> AccountId {
     "staking".to_string()
 }
@@ -15,6 +17,13 @@ pub fn owner() -> AccountId {
     "owner".to_string()
 }
 
+pub fn zero_fee() -> RewardFeeFraction {
+    RewardFeeFraction {
+        numerator: 0,
+        denominator: 1,
+    }
+}
+
 pub fn ntoy(near_amount: Balance) -> Balance {
     near_amount * 10u128.pow(24)
 }
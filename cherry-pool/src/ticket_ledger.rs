@@ -0,0 +1,406 @@
+use crate::*;
+
+/// The odds of an account in the current draw period, assuming no balance changes until the
+/// draw.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WinProbability {
+    /// Time-weighted average number of tickets over the draw period.
+    pub average_tickets: U64,
+    /// Tickets multiplied by the number of epochs they are held in the draw period.
+    pub time_weighted_tickets: U64,
+    pub total_time_weighted_tickets: U64,
+    /// The chance to be drawn for the grand prize in parts per million.
+    pub grand_prize_ppm: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy)]
+struct TicketNode {
+    tickets: u64,
+    /// Time-weighted tickets, only valid in the period the node was last updated in. The slots
+    /// of a node from an older period haven't changed since, so they hold their tickets for the
+    /// whole current period.
+    weight: u64,
+    period_id: u64,
+}
+
+/// Tickets of every account, kept in a Fenwick tree over the accounts. Adding and removing
+/// tickets, and finding the owner of a ticket take `O(log n)` storage reads in the number of
+/// accounts, no matter how many tickets there are.
+///
+/// Draws are weighted by time: every ticket counts once per epoch it is held during the draw
+/// period, so tickets bought right before a draw barely count. The weights are kept as if the
+/// current tickets were held until the end of the period, so adding `n` tickets `k` epochs before
+/// the end adds `n * k` to the weight.
+///
+/// Time-weighted tickets are numbered consecutively following the slots: the ones of the first
+/// slot come first, then the ones of the second slot, and so on.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TicketLedger {
+    /// The account of every slot, in the order the accounts got their first tickets. A released
+    /// slot keeps its last owner until it's reused.
+    slot_owners: Vector<AccountId>,
+    /// The slot of every account that holds one.
+    account_slots: LookupMap<AccountId, u64>,
+    /// Slots released by unregistered accounts.
+    free_slots: Vector<u64>,
+    /// Fenwick tree nodes. The node `i` (1-based) holds the tickets of the slots
+    /// `(i - lowbit(i), i]` and is stored at the index `i - 1`.
+    tree: Vector<TicketNode>,
+    total: u64,
+    total_weight: u64,
+    period_id: u64,
+    period_start: EpochHeight,
+    period_duration: EpochHeight,
+}
+
+impl TicketLedger {
+    pub fn new(prefix: Vec<u8>, period_start: EpochHeight, period_duration: EpochHeight) -> Self {
+        Self {
+            slot_owners: Vector::new([prefix.as_slice(), b"o"].concat()),
+            account_slots: LookupMap::new([prefix.as_slice(), b"s"].concat()),
+            free_slots: Vector::new([prefix.as_slice(), b"f"].concat()),
+            tree: Vector::new([prefix.as_slice(), b"n"].concat()),
+            total: 0,
+            total_weight: 0,
+            period_id: 0,
+            period_start,
+            period_duration,
+        }
+    }
+
+    /// Starts a new draw period, all the current tickets count in full for it.
+    pub fn start_period(&mut self, period_start: EpochHeight, period_duration: EpochHeight) {
+        self.period_id += 1;
+        self.period_start = period_start;
+        self.period_duration = period_duration;
+        self.total_weight = self.total * period_duration;
+    }
+
+    /// The total number of tickets.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// The total number of time-weighted tickets in the current period.
+    pub fn total_weight(&self) -> u64 {
+        self.total_weight
+    }
+
+    /// Whether the account holds a slot, it gets one with its first tickets.
+    pub fn has_slot(&self, account_id: &AccountId) -> bool {
+        self.account_slots.contains_key(account_id)
+    }
+
+    pub fn tickets_of(&self, account_id: &AccountId) -> u64 {
+        match self.account_slots.get(account_id) {
+            Some(slot) => self.slot_tickets(slot).0,
+            None => 0,
+        }
+    }
+
+    pub fn weight_of(&self, account_id: &AccountId) -> u64 {
+        match self.account_slots.get(account_id) {
+            Some(slot) => self.slot_tickets(slot).1,
+            None => 0,
+        }
+    }
+
+    pub fn win_probability(&self, account_id: &AccountId) -> WinProbability {
+        let weight = self.weight_of(account_id);
+        let grand_prize_ppm = if self.total_weight > 0 {
+            (u128::from(weight) * 1_000_000 / u128::from(self.total_weight)) as u64
+        } else {
+            0
+        };
+        WinProbability {
+            average_tickets: (weight / self.period_duration).into(),
+            time_weighted_tickets: weight.into(),
+            total_time_weighted_tickets: self.total_weight.into(),
+            grand_prize_ppm: grand_prize_ppm.into(),
+        }
+    }
+
+    pub fn add(&mut self, account_id: &AccountId, amount: u64) {
+        if amount == 0 {
+            return;
+        }
+        let slot = self.get_or_create_slot(account_id);
+        let weight = amount * self.remaining_epochs();
+        let mut i = slot + 1;
+        while i <= self.tree.len() {
+            let mut node = self.node(i);
+            node.weight = self.node_weight(&node) + weight;
+            node.tickets += amount;
+            node.period_id = self.period_id;
+            self.tree.replace(i - 1, &node);
+            i += lowbit(i);
+        }
+        self.total += amount;
+        self.total_weight += weight;
+    }
+
+    pub fn remove(&mut self, account_id: &AccountId, amount: u64) {
+        if amount == 0 {
+            return;
+        }
+        assert!(
+            self.tickets_of(account_id) >= amount,
+            "Not enough tickets to remove"
+        );
+        let slot = self.account_slots.get(account_id).unwrap();
+        let weight = amount * self.remaining_epochs();
+        let mut i = slot + 1;
+        while i <= self.tree.len() {
+            let mut node = self.node(i);
+            node.weight = self.node_weight(&node) - weight;
+            node.tickets -= amount;
+            node.period_id = self.period_id;
+            self.tree.replace(i - 1, &node);
+            i += lowbit(i);
+        }
+        self.total -= amount;
+        self.total_weight -= weight;
+    }
+
+    /// Releases the slot of an account without tickets, so another account can take it.
+    pub fn release_slot(&mut self, account_id: &AccountId) {
+        if let Some(slot) = self.account_slots.remove(account_id) {
+            assert_eq!(self.slot_tickets(slot).0, 0, "Can't release a slot with tickets");
+            self.free_slots.push(&slot);
+        }
+    }
+
+    /// The account and the tickets of every slot, in the slot order.
+    #[cfg(test)]
+    pub fn slots(&self) -> Vec<(AccountId, u64)> {
+        (0..self.slot_owners.len())
+            .map(|slot| (self.slot_owners.get(slot).unwrap(), self.slot_tickets(slot).0))
+            .collect()
+    }
+
+    /// Returns the owner of the given time-weighted ticket, `ticket` should be less than
+    /// `total_weight()`.
+    pub fn find_owner(&self, ticket: u64) -> AccountId {
+        assert!(ticket < self.total_weight, "The ticket doesn't exist");
+        let len = self.tree.len();
+        // Descends the tree looking for the last node whose prefix sum doesn't exceed `ticket`,
+        // the owner is in the slot right after it.
+        let mut position = 0;
+        let mut remaining = ticket;
+        let mut step = 1u64 << (63 - len.leading_zeros());
+        while step > 0 {
+            if position + step <= len {
+                let weight = self.node_weight(&self.node(position + step));
+                if weight <= remaining {
+                    position += step;
+                    remaining -= weight;
+                }
+            }
+            step >>= 1;
+        }
+        self.slot_owners.get(position).unwrap()
+    }
+
+    fn period_end(&self) -> EpochHeight {
+        self.period_start + self.period_duration
+    }
+
+    /// The number of epochs left in the current period.
+    fn remaining_epochs(&self) -> EpochHeight {
+        let epoch_height = std::cmp::max(env::epoch_height(), self.period_start);
+        self.period_end().saturating_sub(epoch_height)
+    }
+
+    fn node_weight(&self, node: &TicketNode) -> u64 {
+        if node.period_id == self.period_id {
+            node.weight
+        } else {
+            node.tickets * self.period_duration
+        }
+    }
+
+    fn get_or_create_slot(&mut self, account_id: &AccountId) -> u64 {
+        if let Some(slot) = self.account_slots.get(account_id) {
+            return slot;
+        }
+        // A released slot can only be taken once its time-weighted tickets of the current period
+        // are gone, they still belong to its last owner.
+        if let Some(slot) = self.free_slots.get(self.free_slots.len().saturating_sub(1)) {
+            if self.slot_tickets(slot).1 == 0 {
+                self.free_slots.pop();
+                self.slot_owners.replace(slot, account_id);
+                self.account_slots.insert(account_id, &slot);
+                return slot;
+            }
+        }
+        let slot = self.slot_owners.len();
+        let i = slot + 1;
+        // The new node covers `(i - lowbit(i), i]`, all of it but the new slot already exists.
+        let (tickets, weight) = self.prefix_sum(i - 1);
+        let (tickets_before, weight_before) = self.prefix_sum(i - lowbit(i));
+        self.tree.push(&TicketNode {
+            tickets: tickets - tickets_before,
+            weight: weight - weight_before,
+            period_id: self.period_id,
+        });
+        self.slot_owners.push(account_id);
+        self.account_slots.insert(account_id, &slot);
+        slot
+    }
+
+    fn node(&self, i: u64) -> TicketNode {
+        self.tree.get(i - 1).unwrap()
+    }
+
+    /// The number of tickets and time-weighted tickets of the slot.
+    fn slot_tickets(&self, slot: u64) -> (u64, u64) {
+        let (tickets, weight) = self.prefix_sum(slot + 1);
+        let (tickets_before, weight_before) = self.prefix_sum(slot);
+        (tickets - tickets_before, weight - weight_before)
+    }
+
+    /// The number of tickets and time-weighted tickets in the first `i` slots.
+    fn prefix_sum(&self, mut i: u64) -> (u64, u64) {
+        let mut tickets = 0;
+        let mut weight = 0;
+        while i > 0 {
+            let node = self.node(i);
+            tickets += node.tickets;
+            weight += self.node_weight(&node);
+            i -= lowbit(i);
+        }
+        (tickets, weight)
+    }
+}
+
+fn lowbit(i: u64) -> u64 {
+    i & i.wrapping_neg()
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    use crate::test_utils::*;
+
+    use super::*;
+
+    fn carol() -> AccountId {
+        "carol".to_string()
+    }
+
+    fn set_epoch_height(epoch_height: EpochHeight) {
+        testing_env!(VMContextBuilder::new().epoch_height(epoch_height).finish());
+    }
+
+    #[test]
+    fn test_find_owner() {
+        set_epoch_height(0);
+        // A single epoch period weighs every ticket once.
+        let mut ledger = TicketLedger::new(b"t".to_vec(), 0, 1);
+        ledger.add(&alice(), 3);
+        ledger.add(&bob(), 5);
+        ledger.add(&carol(), 2);
+        assert_eq!(ledger.total(), 10);
+        assert_eq!(ledger.total_weight(), 10);
+        assert_eq!(ledger.tickets_of(&bob()), 5);
+
+        let owners: Vec<AccountId> = (0..10).map(|ticket| ledger.find_owner(ticket)).collect();
+        assert_eq!(owners[..3], vec![alice(); 3][..]);
+        assert_eq!(owners[3..8], vec![bob(); 5][..]);
+        assert_eq!(owners[8..], vec![carol(); 2][..]);
+
+        ledger.remove(&bob(), 3);
+        ledger.add(&alice(), 1);
+        assert_eq!(ledger.total(), 8);
+        assert_eq!(ledger.find_owner(3), alice());
+        assert_eq!(ledger.find_owner(4), bob());
+        assert_eq!(ledger.find_owner(5), bob());
+        assert_eq!(ledger.find_owner(6), carol());
+    }
+
+    #[test]
+    fn test_many_slots() {
+        set_epoch_height(0);
+        let mut ledger = TicketLedger::new(b"t".to_vec(), 0, 1);
+        for i in 0..37u64 {
+            ledger.add(&format!("account{}", i), i + 1);
+        }
+        assert_eq!(ledger.total(), 37 * 38 / 2);
+        let mut ticket = 0;
+        for i in 0..37u64 {
+            // A fresh context for every account, the lookups of all of them exceed the gas limit.
+            set_epoch_height(0);
+            assert_eq!(ledger.tickets_of(&format!("account{}", i)), i + 1);
+            for _ in 0..=i {
+                assert_eq!(ledger.find_owner(ticket), format!("account{}", i));
+                ticket += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_time_weighted_tickets() {
+        set_epoch_height(0);
+        let mut ledger = TicketLedger::new(b"t".to_vec(), 0, 10);
+        ledger.add(&alice(), 10);
+
+        set_epoch_height(5);
+        ledger.add(&bob(), 10);
+
+        set_epoch_height(8);
+        ledger.remove(&alice(), 5);
+        assert_eq!(ledger.weight_of(&alice()), 90);
+        assert_eq!(ledger.weight_of(&bob()), 50);
+
+        // Tickets added after the end of the period don't count for it.
+        set_epoch_height(12);
+        ledger.add(&carol(), 10);
+        assert_eq!(ledger.weight_of(&carol()), 0);
+        assert_eq!(ledger.total_weight(), 140);
+        assert_eq!(ledger.find_owner(89), alice());
+        assert_eq!(ledger.find_owner(90), bob());
+        assert_eq!(ledger.find_owner(139), bob());
+        assert_eq!(ledger.win_probability(&bob()).grand_prize_ppm.0, 357_142);
+
+        // In the next period everybody's tickets count in full.
+        ledger.start_period(12, 10);
+        assert_eq!(ledger.total_weight(), 250);
+        assert_eq!(ledger.weight_of(&alice()), 50);
+        assert_eq!(ledger.find_owner(49), alice());
+        assert_eq!(ledger.find_owner(50), bob());
+        assert_eq!(ledger.find_owner(150), carol());
+    }
+
+    #[test]
+    fn test_release_slot() {
+        set_epoch_height(0);
+        let mut ledger = TicketLedger::new(b"t".to_vec(), 0, 10);
+        ledger.add(&alice(), 3);
+        set_epoch_height(5);
+        ledger.remove(&alice(), 3);
+        ledger.release_slot(&alice());
+        assert!(!ledger.has_slot(&alice()));
+
+        // The slot still holds time-weighted tickets of the current period.
+        ledger.add(&bob(), 2);
+        assert_eq!(ledger.find_owner(14), alice());
+        assert_eq!(ledger.slots(), vec![(alice(), 0), (bob(), 2)]);
+
+        set_epoch_height(10);
+        ledger.start_period(10, 10);
+        ledger.add(&carol(), 1);
+        assert_eq!(ledger.slots(), vec![(carol(), 1), (bob(), 2)]);
+        assert_eq!(ledger.find_owner(9), carol());
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough tickets to remove")]
+    fn test_remove_too_many() {
+        set_epoch_height(0);
+        let mut ledger = TicketLedger::new(b"t".to_vec(), 0, 1);
+        ledger.add(&alice(), 3);
+        ledger.remove(&alice(), 4);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::*;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::Gas;
+
+const GAS_FOR_FT_TRANSFER: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_TOKEN_SENT: Gas = 10_000_000_000_000;
+const ONE_YOCTO: Balance = 1;
+const NO_DEPOSIT: Balance = 0;
+
+#[ext_contract(ext_fungible_token)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+/// A pool configured with a `deposit_token_id` takes its deposits in that token with
+/// `ft_transfer_call`. The `msg` tells what to do with the tokens:
+/// - `""` or `"deposit"` adds them to the unstaked balance of the sender,
+/// - `"deposit_and_stake"` also stakes them and buys tickets,
+/// - `"yield"` funds the prize pot, only the yield source can send it.
+#[near_bindgen]
+impl FungibleTokenReceiver for StakingPool {
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: ValidAccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token_id = self
+            .config
+            .deposit_token_id
+            .clone()
+            .unwrap_or_else(|| env::panic(b"The pool only accepts NEAR deposits"));
+        assert_eq!(
+            env::predecessor_account_id(),
+            token_id,
+            "The pool only accepts {} deposits",
+            token_id
+        );
+        let sender_id: AccountId = sender_id.into();
+        let amount: Balance = amount.into();
+        assert!(amount > 0, "Deposit amount should be positive");
+        self.internal_ping();
+
+        match msg.as_str() {
+            "" | "deposit" => {
+                self.assert_deposits_allowed();
+                self.internal_token_deposit(&sender_id, amount);
+            }
+            "deposit_and_stake" => {
+                self.assert_deposits_allowed();
+                self.internal_token_deposit(&sender_id, amount);
+                self.internal_stake(&sender_id, amount);
+                self.internal_add_tickets(&sender_id, amount);
+            }
+            "yield" => {
+                assert_eq!(
+                    self.config.yield_source,
+                    YieldSource::TokenTransfers {
+                        account_id: sender_id
+                    },
+                    "Only the yield source can fund the prize pot"
+                );
+                self.internal_distribute_reward(amount);
+            }
+            _ => env::panic(b"Unknown deposit message"),
+        }
+        PromiseOrValue::Value(U128(0))
+    }
+}
+
+#[near_bindgen]
+impl StakingPool {
+    /// Callback of sending deposit tokens. The tokens of a failed transfer go back to the unstaked
+    /// balance of the account, the account is only removed once the transfer succeeded.
+    pub fn on_token_sent(&mut self, account_id: AccountId, amount: U128) {
+        assert_eq!(
+            env::current_account_id(),
+            env::predecessor_account_id(),
+            "Can be called only as a callback"
+        );
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            self.internal_remove_account_if_empty(&account_id);
+            return;
+        }
+        let mut account = self.internal_get_account(account_id);
+        account.unstaked_balance += amount.0;
+        self.save_account(&account);
+        log!(
+            "Sending {} to @{} failed, it's back in the unstaked balance",
+            amount.0,
+            account.account_id
+        );
+    }
+}
+
+impl StakingPool {
+    pub(crate) fn is_token_pool(&self) -> bool {
+        self.config.deposit_token_id.is_some()
+    }
+
+    pub(crate) fn assert_native_pool(&self) {
+        if let Some(token_id) = &self.config.deposit_token_id {
+            env::panic(format!("The pool only accepts {} deposits", token_id).as_bytes());
+        }
+    }
+
+    fn internal_token_deposit(&mut self, account_id: &AccountId, amount: Balance) {
+        self.assert_registered(account_id);
+        self.internal_record_deposit(account_id, amount);
+        let mut account = self.internal_get_account(account_id.clone());
+        account.unstaked_balance += amount;
+        self.save_account(&account);
+        PoolEvent::Deposit(vec![AmountData {
+            account_id: account_id.clone(),
+            amount: amount.into(),
+        }])
+        .emit();
+    }
+
+    /// Sends the given amount to the account in NEAR or in the deposit token, then removes the
+    /// account if it's empty. A token transfer can fail, so its account is removed in the callback.
+    pub(crate) fn internal_send(&mut self, account_id: AccountId, amount: Balance) {
+        match self.config.deposit_token_id.clone() {
+            None => {
+                self.last_total_balance -= amount;
+                Promise::new(account_id.clone()).transfer(amount);
+                self.internal_remove_account_if_empty(&account_id);
+            }
+            Some(token_id) => {
+                ext_fungible_token::ft_transfer(
+                    account_id.clone(),
+                    amount.into(),
+                    None,
+                    &token_id,
+                    ONE_YOCTO,
+                    GAS_FOR_FT_TRANSFER,
+                )
+                .then(ext_self::on_token_sent(
+                    account_id,
+                    amount.into(),
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_ON_TOKEN_SENT,
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::*;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::Gas;
+
+/// The version of the `StakingPool` layout this code reads and writes.
+pub const STATE_VERSION: u32 = 1;
+/// The version is stored next to the state, so `migrate` knows which layout it has to read before
+/// deserializing it. The pools deployed before the versioning have no version, it's 0 for them.
+pub(crate) const STATE_VERSION_KEY: &[u8] = b"VERSION";
+/// Gas kept by `upgrade` itself, the rest goes to `migrate`.
+const GAS_FOR_UPGRADE: Gas = 20_000_000_000_000;
+const NO_DEPOSIT: Balance = 0;
+
+#[near_bindgen]
+impl StakingPool {
+    /// Owner's method.
+    /// Deploys the given code to the pool account and calls `migrate` of the new code in the same
+    /// batch, so a failed migration reverts the deployment too.
+    pub fn upgrade(&mut self, code: Base64VecU8) -> Promise {
+        self.assert_owner();
+        assert!(
+            env::prepaid_gas() > env::used_gas() + GAS_FOR_UPGRADE,
+            "Not enough gas to migrate the state"
+        );
+        let gas_for_migrate = env::prepaid_gas() - env::used_gas() - GAS_FOR_UPGRADE;
+        log!("Upgrading the pool from the state version {}", STATE_VERSION);
+        Promise::new(env::current_account_id())
+            .deploy_contract(code.into())
+            .function_call(b"migrate".to_vec(), vec![], NO_DEPOSIT, gas_for_migrate)
+    }
+
+    /// Reads the state in the layout of its stored version and converts it to the current one.
+    /// Can only be called by the pool itself, normally from `upgrade`.
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Can only be called by the pool itself"
+        );
+        let version = read_state_version();
+        let this: StakingPool = match version {
+            0 => read_state::<StakingPoolV0>().into(),
+            STATE_VERSION => read_state(),
+            _ => env::panic(format!("Unknown state version {}", version).as_bytes()),
+        };
+        write_state_version();
+        log!("Migrated the state from version {} to {}", version, STATE_VERSION);
+        this
+    }
+
+    pub fn get_state_version(&self) -> u32 {
+        read_state_version()
+    }
+
+    /// Owner's or operator's method.
+    /// Moves the tickets of up to `limit` legacy ticket slots of a pool deployed before the
+    /// versioning to the ticket ledger, converting their accounts. Accounts can't change and no
+    /// draws happen until all the slots are moved. Returns the number of slots left.
+    pub fn migrate_legacy_tickets(&mut self, limit: U64) -> U64 {
+        self.assert_owner_or_role(&[Role::Operator]);
+        let mut legacy_ticket_slots = self
+            .legacy_ticket_slots
+            .take()
+            .unwrap_or_else(|| env::panic(b"There are no legacy tickets to migrate"));
+        for _ in 0..limit.0 {
+            match legacy_ticket_slots.pop() {
+                Some(account_id) => self.internal_migrate_legacy_account(&account_id),
+                None => break,
+            }
+        }
+        let num_slots_left = legacy_ticket_slots.len();
+        if num_slots_left > 0 {
+            self.legacy_ticket_slots = Some(legacy_ticket_slots);
+        } else {
+            log!("The legacy tickets are migrated");
+        }
+        num_slots_left.into()
+    }
+}
+
+impl StakingPool {
+    /// Whether the account is registered, the accounts of the pools deployed before the
+    /// versioning are registered without a storage deposit.
+    pub(crate) fn is_registered(&self, account_id: &AccountId) -> bool {
+        self.accounts.contains_key(account_id)
+            || matches!(&self.legacy_accounts, Some(legacy_accounts) if legacy_accounts.contains_key(account_id))
+    }
+
+    /// Returns the not yet converted account of a pool deployed before the versioning. The
+    /// accounts left out of the legacy ticket slots hold no tickets, they couldn't be drawn.
+    pub(crate) fn internal_legacy_account(&self, account_id: &AccountId) -> Option<StakingPoolAccount> {
+        let legacy_account = self.legacy_accounts.as_ref()?.get(account_id)?;
+        let mut account = StakingPoolAccount::from(legacy_account);
+        if self.legacy_ticket_slots.is_none() {
+            account.tickets_amount = 0;
+        }
+        Some(account)
+    }
+
+    /// Converts the legacy account and adds its tickets to the ledger, if it's not converted yet.
+    fn internal_migrate_legacy_account(&mut self, account_id: &AccountId) {
+        let legacy_account = match self
+            .legacy_accounts
+            .as_mut()
+            .and_then(|legacy_accounts| legacy_accounts.remove(account_id))
+        {
+            Some(legacy_account) => legacy_account,
+            None => return,
+        };
+        let account = StakingPoolAccount::from(legacy_account);
+        self.tickets.add(account_id, account.tickets_amount);
+        self.accounts.insert(account_id, &account);
+    }
+}
+
+pub(crate) fn read_state_version() -> u32 {
+    match env::storage_read(STATE_VERSION_KEY) {
+        Some(value) => {
+            let mut bytes = [0u8; 4];
+            assert_eq!(value.len(), bytes.len(), "The state version is corrupted");
+            bytes.copy_from_slice(&value);
+            u32::from_le_bytes(bytes)
+        }
+        None => 0,
+    }
+}
+
+/// Reads the state in the given layout.
+fn read_state<T: BorshDeserialize>() -> T {
+    env::state_read().unwrap_or_else(|| env::panic(b"The pool is not initialized"))
+}
+
+pub(crate) fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &STATE_VERSION.to_le_bytes());
+}
+
+/// The layout of the pools deployed before the versioning. The accounts have their own layout
+/// too, they are converted one by one as they're saved, or as their tickets are moved to the
+/// ticket ledger by `migrate_legacy_tickets`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct StakingPoolV0 {
+    owner_id: AccountId,
+    accounts: LookupMap<String, StakingPoolAccountV0>,
+    tickets_count: u64,
+    /// One slot per ticket.
+    tickets_random_slots: Vector<AccountId>,
+    next_prize_event_epoch_height: EpochHeight,
+    total_reward: Balance,
+    total_staked_balance: Balance,
+    last_total_balance: Balance,
+    total_stake_shares: Balance,
+    stake_public_key: PublicKey,
+    last_epoch_height: EpochHeight,
+    is_restake_paused: bool,
+    nft_contract_id: AccountId,
+    nft_hold: Vector<TokenId>,
+}
+
+/// The layout of the accounts of the pools deployed before the versioning.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub(crate) struct StakingPoolAccountV0 {
+    account_id: AccountId,
+    unstaked_balance: Balance,
+    staked_balance: Balance,
+    stake_shares: Balance,
+    stake_points: u64,
+    tickets_amount: u64,
+    tickets_multiplier: u64,
+    can_withdraw: bool,
+    unstaked_available_epoch_height: EpochHeight,
+}
+
+impl From<StakingPoolAccountV0> for StakingPoolAccount {
+    fn from(old: StakingPoolAccountV0) -> Self {
+        Self {
+            account_id: old.account_id,
+            unstaked_balance: old.unstaked_balance,
+            staked_balance: old.staked_balance,
+            stake_shares: old.stake_shares,
+            stake_points: old.stake_points,
+            tickets_amount: old.tickets_amount,
+            tickets_multiplier: old.tickets_multiplier,
+            can_withdraw: old.can_withdraw,
+            unstaked_available_epoch_height: old.unstaked_available_epoch_height,
+            pending_prizes: vec![],
+        }
+    }
+}
+
+impl From<StakingPoolV0> for StakingPool {
+    fn from(old: StakingPoolV0) -> Self {
+        let config = PoolConfig::default();
+        // The legacy pools added the rewards of the prize pot to the total staked balance too.
+        let total_staked_balance = old.total_staked_balance - old.total_reward;
+        let legacy_ticket_slots = if old.tickets_random_slots.is_empty() {
+            None
+        } else {
+            Some(old.tickets_random_slots)
+        };
+        Self {
+            owner_id: old.owner_id,
+            proposed_owner_id: None,
+            operators: UnorderedSet::new(b"o".to_vec()),
+            guardians: UnorderedSet::new(b"g".to_vec()),
+            // The converted accounts and the ticket ledger can't share the prefixes of the legacy
+            // accounts and ticket slots.
+            accounts: LookupMap::new(b"A".to_vec()),
+            storage_deposits: LookupMap::new(b"s".to_vec()),
+            reward_fee_fraction: RewardFeeFraction {
+                numerator: 0,
+                denominator: 1,
+            },
+            treasury: None,
+            tickets: TicketLedger::new(b"T".to_vec(), env::epoch_height(), config.draw_period_epochs),
+            config,
+            next_prize_event_epoch_height: old.next_prize_event_epoch_height,
+            total_reward: old.total_reward,
+            total_staked_balance,
+            last_total_balance: old.last_total_balance,
+            total_stake_shares: old.total_stake_shares,
+            stake_public_key: old.stake_public_key,
+            last_epoch_height: old.last_epoch_height,
+            is_restake_paused: old.is_restake_paused,
+            is_deposit_paused: false,
+            is_draw_paused: false,
+            is_emergency: false,
+            delegation: Delegation {
+                reserved_balance: total_staked_balance,
+                ..Default::default()
+            },
+            nft_contract_id: old.nft_contract_id,
+            nft_hold: old.nft_hold,
+            next_draw_id: 0,
+            draws: Vector::new(b"d".to_vec()),
+            account_wins: LookupMap::new(b"w".to_vec()),
+            draw_reveal_delay: None,
+            pending_draw: None,
+            pending_share_transfers: 0,
+            prize_tiers: vec![FULL_PRIZE_BASIS_POINTS],
+            unclaimed_prizes: LookupMap::new(b"u".to_vec()),
+            total_unclaimed_prizes: 0,
+            prizes_expired_before_draw_id: 0,
+            nft_depositors: UnorderedMap::new(b"e".to_vec()),
+            nft_multipliers: default_nft_multipliers(),
+            nft_boosts: LookupMap::new(b"n".to_vec()),
+            unreturned_nfts: LookupMap::new(b"f".to_vec()),
+            loyalty_rules: LoyaltyRules::default(),
+            loyalty_rewards: default_loyalty_rewards(),
+            loyalty_accounts: LookupMap::new(b"l".to_vec()),
+            reward_nfts: LookupMap::new(b"r".to_vec()),
+            account_achievements: LookupMap::new(b"c".to_vec()),
+            legacy_accounts: Some(old.accounts),
+            legacy_ticket_slots,
+        }
+    }
+}
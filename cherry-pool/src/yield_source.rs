@@ -0,0 +1,230 @@
+use crate::*;
+use near_sdk::Gas;
+
+const GAS_FOR_EXTERNAL_ACTION: Gas = 50_000_000_000_000;
+const GAS_FOR_EXTERNAL_VIEW: Gas = 10_000_000_000_000;
+const GAS_FOR_CALLBACK: Gas = 20_000_000_000_000;
+const NO_DEPOSIT: Balance = 0;
+/// External staking pools release unstaked balance after this many epochs.
+const EXTERNAL_NUM_EPOCHS_TO_UNLOCK: EpochHeight = 4;
+
+/// Where the yield that funds the prize pot comes from.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub enum YieldSource {
+    /// The pool is a validator and stakes its balance with its own `stake_public_key`. The reward
+    /// is the growth of the pool balance.
+    #[default]
+    NativeStaking,
+    /// The pool delegates its stake to an external staking pool contract, so it can run without
+    /// a validator node. The reward is the growth of the delegated balance.
+    ExternalStakingPool { pool_id: AccountId },
+    /// The yield of a token pool is sent with `ft_transfer_call` by the given account.
+    TokenTransfers { account_id: AccountId },
+}
+
+/// The stake delegated to an external staking pool as the pool last knew it.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Default)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Delegation {
+    /// The balance kept in the contract to pay for its storage, it's never delegated.
+    #[serde(with = "crate::serialize::u128_dec_format")]
+    pub reserved_balance: Balance,
+    #[serde(with = "crate::serialize::u128_dec_format")]
+    pub staked_balance: Balance,
+    #[serde(with = "crate::serialize::u128_dec_format")]
+    pub unstaked_balance: Balance,
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub unstaked_available_epoch_height: EpochHeight,
+    /// The number of calls to the external pool waiting for their callbacks.
+    pub pending_actions: u32,
+    /// Incremented with every call that changes the delegated balance. A balance query is only
+    /// trusted if no such call was made while it was in flight.
+    #[serde(with = "crate::serialize::u64_dec_format")]
+    pub nonce: u64,
+}
+
+#[ext_contract(ext_staking_pool)]
+pub trait ExternalStakingPool {
+    fn deposit_and_stake(&mut self);
+    fn unstake(&mut self, amount: U128);
+    fn withdraw(&mut self, amount: U128);
+    fn get_account_staked_balance(&self, account_id: AccountId) -> U128;
+}
+
+#[ext_contract(ext_delegation)]
+pub trait DelegationCallbacks {
+    fn on_delegation_staked(&mut self, amount: U128);
+    fn on_delegation_unstaked(&mut self, amount: U128);
+    fn on_delegation_withdrawn(&mut self, amount: U128);
+    fn on_delegation_balance(&mut self, nonce: U64) -> bool;
+}
+
+#[near_bindgen]
+impl StakingPool {
+    /// Callback of `deposit_and_stake` on the external pool. The deposit is refunded on failure.
+    pub fn on_delegation_staked(&mut self, amount: U128) {
+        self.assert_delegation_callback();
+        self.finish_delegation_action();
+        if !is_promise_success() {
+            self.delegation.staked_balance -= amount.0;
+            log!("Delegating {} to the external pool failed", amount.0);
+        }
+    }
+
+    /// Callback of `unstake` on the external pool.
+    pub fn on_delegation_unstaked(&mut self, amount: U128) {
+        self.assert_delegation_callback();
+        self.finish_delegation_action();
+        if !is_promise_success() {
+            self.delegation.staked_balance += amount.0;
+            self.delegation.unstaked_balance -= amount.0;
+            log!("Unstaking {} from the external pool failed", amount.0);
+        }
+    }
+
+    /// Callback of `withdraw` on the external pool.
+    pub fn on_delegation_withdrawn(&mut self, amount: U128) {
+        self.assert_delegation_callback();
+        self.finish_delegation_action();
+        if !is_promise_success() {
+            self.delegation.unstaked_balance += amount.0;
+            log!("Withdrawing {} from the external pool failed", amount.0);
+        }
+    }
+
+    /// Callback of the delegated balance query. The growth of the delegated balance is the reward
+    /// of the epoch. Returns whether the balance was taken into account.
+    pub fn on_delegation_balance(&mut self, nonce: U64) -> bool {
+        self.assert_delegation_callback();
+        let staked_balance: Balance = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                match near_sdk::serde_json::from_slice::<U128>(&value) {
+                    Ok(staked_balance) => staked_balance.0,
+                    Err(_) => return false,
+                }
+            }
+            _ => return false,
+        };
+        // The delegated balance changed while the query was in flight, the reward is picked up on
+        // the next epoch.
+        if nonce.0 != self.delegation.nonce || self.delegation.pending_actions > 0 {
+            return false;
+        }
+        if staked_balance > self.delegation.staked_balance {
+            let reward = staked_balance - self.delegation.staked_balance;
+            self.delegation.staked_balance = staked_balance;
+            self.internal_distribute_reward(reward);
+            // Unstakes the new prize pot so it becomes liquid for prize claims.
+            self.internal_restake();
+        }
+        true
+    }
+}
+
+impl StakingPool {
+    pub(crate) fn is_native_staking(&self) -> bool {
+        self.config.yield_source == YieldSource::NativeStaking
+    }
+
+    /// Collects the yield of the new epoch from an external staking pool and pulls back the
+    /// balance unstaked from it. Native rewards are collected by `internal_ping` itself.
+    pub(crate) fn internal_collect_external_yield(&mut self) {
+        let pool_id = match &self.config.yield_source {
+            YieldSource::ExternalStakingPool { pool_id } => pool_id.clone(),
+            _ => return,
+        };
+        if self.delegation.unstaked_balance > 0
+            && self.delegation.unstaked_available_epoch_height <= env::epoch_height()
+        {
+            let amount = self.delegation.unstaked_balance;
+            self.delegation.unstaked_balance = 0;
+            self.start_delegation_action();
+            ext_staking_pool::withdraw(amount.into(), &pool_id, NO_DEPOSIT, GAS_FOR_EXTERNAL_ACTION)
+                .then(ext_delegation::on_delegation_withdrawn(
+                    amount.into(),
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_CALLBACK,
+                ));
+        }
+        if self.delegation.pending_actions == 0 {
+            ext_staking_pool::get_account_staked_balance(
+                env::current_account_id(),
+                &pool_id,
+                NO_DEPOSIT,
+                GAS_FOR_EXTERNAL_VIEW,
+            )
+            .then(ext_delegation::on_delegation_balance(
+                self.delegation.nonce.into(),
+                &env::current_account_id(),
+                NO_DEPOSIT,
+                GAS_FOR_CALLBACK + GAS_FOR_EXTERNAL_ACTION,
+            ));
+        }
+    }
+
+    /// Moves the delegated stake towards the total staked balance of the pool.
+    pub(crate) fn internal_restake_external(&mut self, pool_id: AccountId) {
+        let target = self
+            .total_staked_balance
+            .saturating_sub(self.delegation.reserved_balance);
+        let staked_balance = self.delegation.staked_balance;
+        if target > staked_balance {
+            let amount = std::cmp::min(target - staked_balance, env::account_balance());
+            if amount == 0 {
+                return;
+            }
+            self.delegation.staked_balance += amount;
+            self.start_delegation_action();
+            ext_staking_pool::deposit_and_stake(&pool_id, amount, GAS_FOR_EXTERNAL_ACTION).then(
+                ext_delegation::on_delegation_staked(
+                    amount.into(),
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_CALLBACK,
+                ),
+            );
+        } else if target < staked_balance {
+            let amount = staked_balance - target;
+            self.delegation.staked_balance -= amount;
+            self.delegation.unstaked_balance += amount;
+            self.delegation.unstaked_available_epoch_height =
+                env::epoch_height() + EXTERNAL_NUM_EPOCHS_TO_UNLOCK;
+            self.start_delegation_action();
+            ext_staking_pool::unstake(amount.into(), &pool_id, NO_DEPOSIT, GAS_FOR_EXTERNAL_ACTION)
+                .then(ext_delegation::on_delegation_unstaked(
+                    amount.into(),
+                    &env::current_account_id(),
+                    NO_DEPOSIT,
+                    GAS_FOR_CALLBACK,
+                ));
+        }
+    }
+
+    fn start_delegation_action(&mut self) {
+        self.delegation.pending_actions += 1;
+        self.delegation.nonce += 1;
+    }
+
+    fn finish_delegation_action(&mut self) {
+        self.delegation.pending_actions = self.delegation.pending_actions.saturating_sub(1);
+    }
+
+    fn assert_delegation_callback(&self) {
+        assert_eq!(
+            env::current_account_id(),
+            env::predecessor_account_id(),
+            "Can be called only as a callback"
+        );
+    }
+}
+
+fn is_promise_success() -> bool {
+    assert_eq!(
+        env::promise_results_count(),
+        1,
+        "Contract expected a result on the callback"
+    );
+    matches!(env::promise_result(0), PromiseResult::Successful(_))
+}
@@ -19,10 +19,12 @@ use near_contract_standards::non_fungible_token::metadata::{
     NFTContractMetadata, NonFungibleTokenMetadataProvider, TokenMetadata, NFT_METADATA_SPEC,
 };
 use near_contract_standards::non_fungible_token::{Token, TokenId};
-use near_contract_standards::non_fungible_token::NonFungibleToken;
+use near_contract_standards::non_fungible_token::core::StorageKey as TokensStorageKey;
+use near_contract_standards::non_fungible_token::{refund_deposit, NonFungibleToken};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::collections::*;
-use near_sdk::json_types::ValidAccountId;
+use near_sdk::json_types::{ValidAccountId, U64};
+use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
     env, near_bindgen, wee_alloc, AccountId, BorshStorageKey, PanicOnDefault, Promise, PromiseOrValue, Gas
 };
@@ -35,6 +37,25 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 pub struct Contract {
     tokens: NonFungibleToken,
     metadata: LazyOption<NFTContractMetadata>,
+    /// The accounts allowed to mint the tokens of the series, like the Cherry Pub pools.
+    minters: UnorderedSet<AccountId>,
+    series: UnorderedMap<String, Series>,
+}
+
+/// The layout of the contract before the minters and the series.
+#[derive(BorshDeserialize)]
+struct OldContract {
+    tokens: NonFungibleToken,
+    metadata: LazyOption<NFTContractMetadata>,
+}
+
+/// A series of tokens with the same metadata, minted on demand up to `max_supply` tokens.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Series {
+    pub metadata: TokenMetadata,
+    pub max_supply: U64,
+    pub minted: U64,
 }
 
 const DATA_IMAGE_SVG_NEAR_ICON: &str = "data:image/svg+xml,%3Csvg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 288 288'%3E%3Cg id='l' data-name='l'%3E%3Cpath d='M187.58,79.81l-30.1,44.69a3.2,3.2,0,0,0,4.75,4.2L191.86,103a1.2,1.2,0,0,1,2,.91v80.46a1.2,1.2,0,0,1-2.12.77L102.18,77.93A15.35,15.35,0,0,0,90.47,72.5H87.34A15.34,15.34,0,0,0,72,87.84V201.16A15.34,15.34,0,0,0,87.34,216.5h0a15.35,15.35,0,0,0,13.08-7.31l30.1-44.69a3.2,3.2,0,0,0-4.75-4.2L96.14,186a1.2,1.2,0,0,1-2-.91V104.61a1.2,1.2,0,0,1,2.12-.77l89.55,107.23a15.35,15.35,0,0,0,11.71,5.43h3.13A15.34,15.34,0,0,0,216,201.16V87.84A15.34,15.34,0,0,0,200.66,72.5h0A15.35,15.35,0,0,0,187.58,79.81Z'/%3E%3C/g%3E%3C/svg%3E";
@@ -47,6 +68,8 @@ enum StorageKey {
     TokenMetadata,
     Enumeration,
     Approval,
+    Minters,
+    Series,
 }
 
 #[near_bindgen]
@@ -82,6 +105,26 @@ impl Contract {
                 Some(StorageKey::Approval),
             ),
             metadata: LazyOption::new(StorageKey::Metadata, Some(&metadata)),
+            minters: UnorderedSet::new(StorageKey::Minters),
+            series: UnorderedMap::new(StorageKey::Series),
+        }
+    }
+
+    /// Adds the minters and the series to the state of the contract deployed before them.
+    /// Can only be called by the contract itself.
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        assert_eq!(
+            env::predecessor_account_id(),
+            env::current_account_id(),
+            "Can only be called by the contract itself"
+        );
+        let old: OldContract = env::state_read().expect("The contract is not initialized");
+        Self {
+            tokens: old.tokens,
+            metadata: old.metadata,
+            minters: UnorderedSet::new(StorageKey::Minters),
+            series: UnorderedMap::new(StorageKey::Series),
         }
     }
 
@@ -119,7 +162,7 @@ impl Contract {
             expires_at: None,
             starts_at: None,
             updated_at: None,
-            extra: None,
+            extra: Some(r#"{"rarity":"legend"}"#.into()),
             reference: None,
             reference_hash: None
         };
@@ -138,7 +181,7 @@ impl Contract {
             expires_at: None,
             starts_at: None,
             updated_at: None,
-            extra: None,
+            extra: Some(r#"{"rarity":"rare"}"#.into()),
             reference: None,
             reference_hash: None
         };
@@ -157,7 +200,7 @@ impl Contract {
             expires_at: None,
             starts_at: None,
             updated_at: None,
-            extra: None,
+            extra: Some(r#"{"rarity":"common"}"#.into()),
             reference: None,
             reference_hash: None
         };
@@ -168,6 +211,95 @@ impl Contract {
 
         i
     }
+
+    /// Allows `account_id` to mint the tokens of the series. Only the owner can add minters.
+    pub fn add_minter(&mut self, account_id: ValidAccountId) {
+        self.assert_owner();
+        self.minters.insert(account_id.as_ref());
+    }
+
+    pub fn remove_minter(&mut self, account_id: ValidAccountId) {
+        self.assert_owner();
+        self.minters.remove(account_id.as_ref());
+    }
+
+    pub fn get_minters(&self) -> Vec<AccountId> {
+        self.minters.to_vec()
+    }
+
+    /// Creates the series `series_id`, at most `max_supply` of its tokens can ever be minted.
+    pub fn create_series(&mut self, series_id: String, metadata: TokenMetadata, max_supply: U64) {
+        self.assert_owner();
+        assert!(!series_id.contains(':'), "The series id can't contain ':'");
+        assert!(max_supply.0 > 0, "The max supply should be positive");
+        assert!(self.series.get(&series_id).is_none(), "The series {} already exists", series_id);
+        let series = Series {
+            metadata: TokenMetadata {
+                copies: Some(max_supply.0),
+                ..metadata
+            },
+            max_supply,
+            minted: U64(0),
+        };
+        self.series.insert(&series_id, &series);
+    }
+
+    pub fn get_series(&self, series_id: String) -> Option<Series> {
+        self.series.get(&series_id)
+    }
+
+    /// Mints the next token of the series to `receiver_id`, its id is `<series_id>:<number>`.
+    /// Only the owner and the minters can mint, and the caller pays the storage like `nft_mint`.
+    #[payable]
+    pub fn nft_mint_series(&mut self, series_id: String, receiver_id: ValidAccountId) -> Token {
+        let minter_id = env::predecessor_account_id();
+        assert!(
+            minter_id == self.tokens.owner_id || self.minters.contains(&minter_id),
+            "Only the minters can mint the series tokens"
+        );
+        let mut series = self
+            .series
+            .get(&series_id)
+            .unwrap_or_else(|| env::panic(format!("Unknown series {}", series_id).as_bytes()));
+        assert!(series.minted.0 < series.max_supply.0, "The series {} is sold out", series_id);
+        series.minted = U64(series.minted.0 + 1);
+        self.series.insert(&series_id, &series);
+
+        let token_id = format!("{}:{}", series_id, series.minted.0);
+        self.internal_mint(token_id, receiver_id.into(), series.metadata)
+    }
+
+    /// Mints the token like `self.tokens.mint`, which only lets the owner mint, so the caller
+    /// checks who can mint. The attached deposit pays the storage of the token.
+    fn internal_mint(&mut self, token_id: TokenId, owner_id: AccountId, metadata: TokenMetadata) -> Token {
+        let initial_storage_usage = env::storage_usage();
+        assert!(self.tokens.owner_by_id.get(&token_id).is_none(), "token_id must be unique");
+        self.tokens.owner_by_id.insert(&token_id, &owner_id);
+        if let Some(token_metadata_by_id) = &mut self.tokens.token_metadata_by_id {
+            token_metadata_by_id.insert(&token_id, &metadata);
+        }
+        if let Some(tokens_per_owner) = &mut self.tokens.tokens_per_owner {
+            let mut token_ids = tokens_per_owner.get(&owner_id).unwrap_or_else(|| {
+                UnorderedSet::new(TokensStorageKey::TokensPerOwner {
+                    account_hash: env::sha256(owner_id.as_bytes()),
+                })
+            });
+            token_ids.insert(&token_id);
+            tokens_per_owner.insert(&owner_id, &token_ids);
+        }
+        let approved_account_ids = self.tokens.approvals_by_id.as_ref().map(|_| HashMap::new());
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+        Token {
+            token_id,
+            owner_id,
+            metadata: Some(metadata),
+            approved_account_ids,
+        }
+    }
+
+    fn assert_owner(&self) {
+        assert_eq!(env::predecessor_account_id(), self.tokens.owner_id, "Only the owner can call this method");
+    }
 }
 
 near_contract_standards::impl_non_fungible_token_core!(Contract, tokens);
@@ -490,5 +622,70 @@ mod tests {
         
         assert_eq!(token.approved_account_ids.unwrap(), HashMap::new());
     }
+
+    fn create_sample_series(contract: &mut Contract, context: &mut VMContextBuilder, max_supply: u64) {
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        contract.create_series("first_win".to_owned(), sample_token_metadata(), U64(max_supply));
+        contract.add_minter(accounts(2));
+    }
+
+    #[test]
+    fn test_mint_series() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        create_sample_series(&mut contract, &mut context, 2);
+
+        testing_env!(
+            context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(MINT_STORAGE_COST * 2)
+            .build()
+        );
+        let nft = contract.nft_mint_series("first_win".to_owned(), accounts(1));
+        assert_eq!(nft.token_id, "first_win:1".to_owned());
+        assert_eq!(nft.owner_id, accounts(1).to_string());
+        assert_eq!(nft.metadata.unwrap().copies, Some(2));
+        assert_eq!(contract.tokens.owner_id, accounts(0).to_string());
+
+        let nft = contract.nft_mint_series("first_win".to_owned(), accounts(3));
+        assert_eq!(nft.token_id, "first_win:2".to_owned());
+        assert_eq!(contract.get_series("first_win".to_owned()).unwrap().minted, U64(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "The series first_win is sold out")]
+    fn test_mint_series_sold_out() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        create_sample_series(&mut contract, &mut context, 1);
+
+        testing_env!(
+            context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(MINT_STORAGE_COST * 2)
+            .build()
+        );
+        contract.nft_mint_series("first_win".to_owned(), accounts(1));
+        contract.nft_mint_series("first_win".to_owned(), accounts(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the minters can mint the series tokens")]
+    fn test_mint_series_not_minter() {
+        let mut context = get_context(accounts(0));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(0));
+        create_sample_series(&mut contract, &mut context, 1);
+
+        testing_env!(
+            context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(MINT_STORAGE_COST)
+            .build()
+        );
+        contract.nft_mint_series("first_win".to_owned(), accounts(3));
+    }
 }
 